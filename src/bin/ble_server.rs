@@ -1,19 +1,61 @@
-use std::sync::{ Arc, Mutex };
-use rgb::RGB8;
-use rust_embedded_study::{ init, led::WS2812RMT };
-use esp32_nimble::{ utilities::BleUuid, BLEAdvertisementData, BLEDevice, NimbleProperties };
+use std::sync::{ mpsc::channel, Arc, Mutex };
+
+use anyhow::anyhow;
+use rust_embedded_study::{
+    init,
+    led::{
+        control::{ parse_color, parse_marquee },
+        effect::{ EffectConfig, EffectHandle, EffectRequest, EffectRunner },
+        scene::SceneStore,
+        Scene,
+        WS2812RMT,
+    },
+    wifi::{ networks::NetworkStore, portal, scan::scan, KnownNetwork, WifiManager },
+};
+use serde::Deserialize;
+use esp32_nimble::{
+    enums::{ AuthReq, SecurityIOCap },
+    utilities::BleUuid,
+    BLEAdvertisementData,
+    BLEDevice,
+    BLEServer,
+    NimbleProperties,
+};
 
 fn main() -> anyhow::Result<()> {
     // 初始化系统、外设和NVS flash。
-    let (_sys, peripherals, _nvs) = init()?;
+    let (sysloop, mut peripherals, nvs) = init()?;
+
+    // 打开保存场景的NVS命名空间
+    let scenes = Arc::new(Mutex::new(SceneStore::new(nvs.clone())?));
+
+    // 启动WiFi，没有已知网络时等待手机通过配网服务写入
+    let networks = Arc::new(Mutex::new(NetworkStore::new(nvs.clone())?));
+    let known = networks.lock().unwrap().list()?;
+    // 配网可以改写设备连接的网络，只在没有已知网络或者启动时按住按钮时开启
+    let provisioning = known.is_empty() || portal::button_held(&mut peripherals.pins.gpio9)?;
+    let ip_settings = networks.lock().unwrap().ip_settings()?;
+    let wifi = Arc::new(WifiManager::start(peripherals.modem, sysloop, nvs, known, &ip_settings)?);
 
     // 获取BLE设备实例
     let device = BLEDevice::take();
+    // 写入凭据需要加密连接：开启绑定和LE安全连接，设备没有输入输出，使用Just Works配对
+    device
+        .security()
+        .set_auth(AuthReq::Bond | AuthReq::Sc)
+        .set_io_cap(SecurityIOCap::NoInputNoOutput);
+
+    // 初始化LED灯，并交给灯效线程驱动
+    let led = EffectRunner::spawn(
+        WS2812RMT::new(peripherals.pins.gpio8, peripherals.rmt.channel0)?,
+        30
+    )?;
 
-    // 初始化LED灯
-    let led = Arc::new(
-        Mutex::new(WS2812RMT::new(peripherals.pins.gpio8, peripherals.rmt.channel0)?)
-    );
+    // 恢复上一次召回的场景，直接调色或切换灯效不会被记住
+    if let Some(scene) = scenes.lock().unwrap().last()? {
+        log::info!("Restore scene {:?}", scene);
+        led.apply_scene(&scene)?;
+    }
 
     // 获取并配置BLE的广告实例
     let advertising = device.get_advertising();
@@ -51,16 +93,37 @@ fn main() -> anyhow::Result<()> {
         .lock()
         .create_characteristic(BleUuid::from_uuid16(0xffa2), NimbleProperties::WRITE);
 
+    // 在服务中创建一个特性，用于切换灯效，使用UUID 0xffa3，写入的内容为JSON格式的灯效配置（可以指定分段）
+    let effect_characteristic = service
+        .lock()
+        .create_characteristic(BleUuid::from_uuid16(0xffa3), NimbleProperties::WRITE);
+
+    // 在服务中创建一个特性，用于在点阵上滚动显示文字，使用UUID 0xffa4
+    // 写入的内容可以是纯文本，也可以是JSON格式的跑马灯参数
+    let text_characteristic = service
+        .lock()
+        .create_characteristic(BleUuid::from_uuid16(0xffa4), NimbleProperties::WRITE);
+
+    // 在服务中创建一个特性，用于管理场景，使用UUID 0xffa5
+    // 写入JSON格式的场景命令，读取时返回已保存的场景名
+    let scene_characteristic = service
+        .lock()
+        .create_characteristic(
+            BleUuid::from_uuid16(0xffa5),
+            NimbleProperties::WRITE | NimbleProperties::READ
+        );
+
     // 当设置颜色特性被写入时，更新LED的颜色。
     let write_led = led.clone();
     set_color_characteristic
         .lock()
         .on_write(move |args| {
-            let data = args.recv_data();
-            match write_led.lock().unwrap().set_pixel(RGB8::new(data[0], data[1], data[2])) {
-                Ok(_) => {
-                    log::warn!("Set LED color to {:?}", RGB8::new(data[0], data[1], data[2]))
-                }
+            let result = parse_color(args.recv_data()).and_then(|(color, transition)| {
+                write_led.fade_to(color, transition)?;
+                Ok(color)
+            });
+            match result {
+                Ok(color) => { log::warn!("Set LED color to {:?}", color) }
                 Err(e) => log::error!("Error: {}", e),
             }
         })
@@ -69,26 +132,226 @@ fn main() -> anyhow::Result<()> {
             value.set_value(b"hello world")
         });
 
+    // 当灯效特性被写入时，切换灯效。
+    let effect_led = led.clone();
+    effect_characteristic.lock().on_write(move |args| {
+        let result = serde_json
+            ::from_slice::<EffectRequest>(args.recv_data())
+            .map_err(anyhow::Error::from)
+            .and_then(|request| request.apply(&effect_led));
+        match result {
+            Ok(_) => { log::warn!("Set LED effect") }
+            Err(e) => log::error!("Error: {}", e),
+        }
+    });
+
+    // 当文字特性被写入时，切换到跑马灯显示文字。
+    let text_led = led.clone();
+    text_characteristic.lock().on_write(move |args| {
+        let result = parse_marquee(args.recv_data()).and_then(|marquee| {
+            text_led.set_effect(EffectConfig::Marquee(marquee))
+        });
+        match result {
+            Ok(_) => { log::warn!("Set LED text") }
+            Err(e) => log::error!("Error: {}", e),
+        }
+    });
+
+    // 当场景特性被写入时，保存、恢复或删除场景；读取时返回场景列表。
+    let scene_led = led.clone();
+    let scenes_read = scenes.clone();
+    scene_characteristic
+        .lock()
+        .on_write(move |args| {
+            let result = serde_json
+                ::from_slice::<SceneCommand>(args.recv_data())
+                .map_err(anyhow::Error::from)
+                .and_then(|command| handle_scene_command(command, &scenes, &scene_led));
+            match result {
+                Ok(_) => { log::warn!("Handled scene command") }
+                Err(e) => log::error!("Error: {}", e),
+            }
+        })
+        .on_read(move |value, _desc| {
+            match scenes_read.lock().unwrap().list() {
+                Ok(names) => value.set_value(&serde_json::to_vec(&names).unwrap_or_default()),
+                Err(e) => log::error!("Error: {}", e),
+            }
+        });
+
     // 当关闭特性被写入时，关闭LED。
     close_characteristic.lock().on_write(move |args| {
         let data = args.recv_data();
         if data[0] == 1 {
-            match led.lock().unwrap().shutdown() {
+            match led.off() {
                 Ok(_) => { log::warn!("Close LED {:?}", data) }
                 Err(e) => log::error!("Error: {}", e),
             }
         }
     });
 
+    // 创建WiFi服务，开启配网时才能写入凭据
+    wifi_service(server, wifi.clone(), networks, provisioning);
+
     // 配置广告数据并启动广告
     advertising
         .lock()
         .set_data(
-            BLEAdvertisementData::new().name("ESP32").add_service_uuid(BleUuid::from_uuid16(0x8848))
+            BLEAdvertisementData::new()
+                .name("ESP32")
+                .add_service_uuid(BleUuid::from_uuid16(0x8848))
+                .add_service_uuid(BleUuid::from_uuid16(0x884a))
         )?;
     advertising.lock().start()?;
     // 打印蓝牙服务相关日志
     server.ble_gatts_show_local();
 
+    // 保持程序运行，WiFi管理器在main中一直持有，断开后继续自动重连
+    let _wifi = wifi;
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
+
+// 创建WiFi服务，使用UUID 0x884a，手机App不用切换热点就能给设备配网和查看连接诊断
+// 扫描、状态和诊断一直可用，凭据特性只在`provisioning`为true时创建
+// 通知的长度受MTU限制，App需要先协商较大的MTU，或者收到通知后再读取完整的值
+fn wifi_service(
+    server: &mut BLEServer,
+    wifi: Arc<WifiManager>,
+    networks: Arc<Mutex<NetworkStore>>,
+    provisioning: bool
+) {
+    let service = server.create_service(BleUuid::from_uuid16(0x884a));
+
+    // 扫描特性，使用UUID 0xffb1，写入任意值开始扫描，扫描完成后通知JSON格式的扫描结果
+    // 包含所有AP的SSID、BSSID、信道、信号强度和认证方式，按信号从强到弱排序
+    let scan_characteristic = service
+        .lock()
+        .create_characteristic(
+            BleUuid::from_uuid16(0xffb1),
+            NimbleProperties::WRITE | NimbleProperties::READ | NimbleProperties::NOTIFY
+        );
+
+    // 状态特性，使用UUID 0xffb3，连接状态变化时通知，连接成功时包含分配到的IP
+    let status_characteristic = service
+        .lock()
+        .create_characteristic(
+            BleUuid::from_uuid16(0xffb3),
+            NimbleProperties::READ | NimbleProperties::NOTIFY
+        );
+
+    // 诊断特性，使用UUID 0xffb4，读取时返回JSON格式的连接诊断信息
+    let diagnostics_characteristic = service
+        .lock()
+        .create_characteristic(BleUuid::from_uuid16(0xffb4), NimbleProperties::READ);
+
+    // 扫描需要几秒钟，不能阻塞BLE的回调，交给单独的线程
+    let (scan_tx, scan_rx) = channel::<()>();
+    scan_characteristic.lock().on_write(move |_args| {
+        let _ = scan_tx.send(());
+    });
+    let scan_wifi = wifi.clone();
+    std::thread::spawn(move || {
+        while scan_rx.recv().is_ok() {
+            let value = match scan(&mut scan_wifi.wifi()) {
+                Ok(results) => serde_json::to_vec(&results).unwrap_or_default(),
+                Err(e) => {
+                    log::error!("Error: {}", e);
+                    serde_json::to_vec(&serde_json::json!({ "error": e.to_string() })).unwrap_or_default()
+                }
+            };
+            scan_characteristic.lock().set_value(&value).notify();
+        }
+    });
+
+    // 读取时生成最新的诊断信息
+    let diagnostics_wifi = wifi.clone();
+    diagnostics_characteristic.lock().on_read(move |value, _desc| {
+        value.set_value(&serde_json::to_vec(&diagnostics_wifi.diagnostics()).unwrap_or_default());
+    });
+
+    // 凭据特性，使用UUID 0xffb2，写入JSON格式的网络，例如 {"ssid":"lab","password":"secret"}
+    // 只接受加密连接的写入，未配对的手机写入时会先触发配对
+    // 保存写入的网络，并让WiFi管理器立即使用新的列表
+    if provisioning {
+        log::info!("Wi-Fi provisioning enabled");
+        let credentials_characteristic = service
+            .lock()
+            .create_characteristic(
+                BleUuid::from_uuid16(0xffb2),
+                NimbleProperties::WRITE | NimbleProperties::WRITE_ENC
+            );
+        let credentials_wifi = wifi.clone();
+        credentials_characteristic.lock().on_write(move |args| {
+            let result = serde_json
+                ::from_slice::<KnownNetwork>(args.recv_data())
+                .map_err(anyhow::Error::from)
+                .and_then(|network| {
+                    log::info!("Provision Wi-Fi network {}", network.ssid);
+                    let mut store = networks.lock().unwrap();
+                    store.add(network)?;
+                    credentials_wifi.set_networks(store.list()?)
+                });
+            if let Err(e) = result {
+                log::error!("Error: {}", e);
+            }
+        });
+    }
+
+    // 把连接状态同步到状态特性
+    let mut wifi_state = wifi.subscribe();
+    status_characteristic
+        .lock()
+        .set_value(&serde_json::to_vec(&wifi_state.current()).unwrap_or_default());
+    std::thread::spawn(move || {
+        loop {
+            let state = wifi_state.changed();
+            status_characteristic
+                .lock()
+                .set_value(&serde_json::to_vec(&state).unwrap_or_default())
+                .notify();
+        }
+    });
+}
+
+// 场景特性的命令，例如 {"action":"recall","name":"reading"}
+// 保存时不带scene则保存当前的灯效和亮度
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum SceneCommand {
+    Save {
+        name: String,
+        #[serde(default)]
+        scene: Option<Scene>,
+    },
+    Recall {
+        name: String,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+// 执行场景命令，恢复的场景会在下次启动时自动恢复
+fn handle_scene_command(
+    command: SceneCommand,
+    scenes: &Mutex<SceneStore>,
+    led: &EffectHandle
+) -> anyhow::Result<()> {
+    let mut store = scenes.lock().unwrap();
+    match command {
+        SceneCommand::Save { name, scene } => {
+            store.save(&name, &scene.unwrap_or_else(|| led.scene()))?;
+        }
+        SceneCommand::Recall { name } => {
+            let scene = store.load(&name)?.ok_or_else(|| anyhow!("scene {} not found", name))?;
+            led.apply_scene(&scene)?;
+            store.set_last(&scene)?;
+        }
+        SceneCommand::Delete { name } => {
+            store.delete(&name)?;
+        }
+    }
     Ok(())
 }
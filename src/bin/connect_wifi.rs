@@ -6,22 +6,46 @@
 /// 
 /// `wifi_ssid` - WiFi的SSID，默认值为"Wokwi-GUEST"。
 /// `wifi_psk` - WiFi的预共享密钥（PSK），默认为空字符串。
+/// `wifi_hostname` - 主机名模板，`{mac}`替换为MAC地址后三个字节，为空时使用默认模板。
+/// `wifi_ip` - 固定IP地址，为空时使用DHCP。
+/// `wifi_netmask`、`wifi_gateway` - 使用固定IP时的子网掩码和网关。
+/// `wifi_dns` - 使用固定IP时的DNS服务器，多个用逗号分隔。
 pub struct Config {
     #[default("Wokwi-GUEST")]
     wifi_ssid: &'static str,
     #[default("")]
     wifi_psk: &'static str,
+    #[default("")]
+    wifi_hostname: &'static str,
+    #[default("")]
+    wifi_ip: &'static str,
+    #[default("255.255.255.0")]
+    wifi_netmask: &'static str,
+    #[default("")]
+    wifi_gateway: &'static str,
+    #[default("")]
+    wifi_dns: &'static str,
 }
 
 fn main() -> anyhow::Result<()> {
     // 初始化系统循环、外设和NVS闪存。
     let (sysloop, peripherals, nvs) = rust_embedded_study::init()?;
     
+    // 配置文件中的固定IP、DNS和主机名。
+    let ip_settings = rust_embedded_study::wifi::IpSettings::from_config(
+        CONFIG.wifi_hostname,
+        CONFIG.wifi_ip,
+        CONFIG.wifi_netmask,
+        CONFIG.wifi_gateway,
+        CONFIG.wifi_dns,
+    )?;
+
     // 使用配置文件中的WiFi SSID和PSK连接到WiFi。
     // 这里使用了`rust_embedded_study`库提供的`connect_wifi`函数。
     let _wifi = rust_embedded_study::wifi::connect_wifi(
         CONFIG.wifi_ssid,
         &CONFIG.wifi_psk,
+        &ip_settings,
         peripherals.modem,
         sysloop,
         nvs,
@@ -6,8 +6,27 @@ use esp_idf_svc::{
     http::{ server::{ Configuration, EspHttpConnection, Request }, Method },
     io::{ Read, Write },
 };
-use rgb::RGB8;
-use rust_embedded_study::led::WS2812RMT;
+use rust_embedded_study::clock::{ Clock, ClockSettings, ClockStore, LocalTime };
+use rust_embedded_study::led::{
+    control::{ BrightnessRequest, ColorRequest },
+    effect::{ EffectConfig, EffectRequest, EffectRunner, Marquee },
+    scene::SceneStore,
+    Scene,
+    Segment,
+    WS2812RMT,
+};
+use rust_embedded_study::schedule::{ Location, Rule, Schedule, ScheduleStore, Scheduler, SunTimes };
+use rust_embedded_study::wifi::{
+    networks::NetworkStore,
+    mdns::Mdns,
+    portal,
+    scan::scan,
+    ApSettings,
+    IpSettings,
+    KnownNetwork,
+    WifiManager,
+    WifiState,
+};
 use serde::{ Deserialize, Serialize };
 
 // 配置结构体，包含WiFi的SSID和PSK
@@ -22,17 +41,61 @@ pub struct Config {
 fn main() -> anyhow::Result<()> {
     // 初始化系统服务、外设和NVS
     // 初始化系统循环、外设和NVS闪存。
-    let (sysloop, peripherals, nvs) = rust_embedded_study::init()?;
-
-    // 连接到WiFi网络
-    // 使用配置文件中的WiFi SSID和PSK连接到WiFi。
-    let _wifi = rust_embedded_study::wifi::connect_wifi(
-        CONFIG.wifi_ssid,
-        &CONFIG.wifi_psk,
-        peripherals.modem,
-        sysloop,
-        nvs
-    )?;
+    let (sysloop, mut peripherals, nvs) = rust_embedded_study::init()?;
+
+    // 打开保存场景的NVS命名空间
+    let scenes = Arc::new(Mutex::new(SceneStore::new(nvs.clone())?));
+
+    // 连接到WiFi网络，断开后自动重连
+    // 依次尝试NVS中保存的已知网络，第一次启动时保存配置文件中的WiFi SSID和PSK。
+    let networks = Arc::new(Mutex::new(NetworkStore::new(nvs.clone())?));
+    let known = networks.lock().unwrap().list_or_seed(CONFIG.wifi_ssid, CONFIG.wifi_psk)?;
+    // 没有已知网络或者启动时按住按钮，进入配网模式，保存网络后会重启
+    if known.is_empty() || portal::button_held(&mut peripherals.pins.gpio9)? {
+        return portal::run(peripherals.modem, sysloop, nvs);
+    }
+    // 固定IP和主机名也保存在NVS中，通过 /wifi/ip 修改
+    let ip_settings = networks.lock().unwrap().ip_settings()?;
+    // 可以同时开启本地热点，没有上游网络时手机连上热点也能控制LED，默认关闭，通过 /wifi/ap 开启并设置密码
+    let ap_settings = networks.lock().unwrap().ap_settings()?;
+    let wifi = Arc::new(
+        WifiManager::start_mixed(peripherals.modem, sysloop, nvs, known, &ip_settings, &ap_settings)?
+    );
+
+    // 在局域网中宣告 <hostname>.local，不用再从串口日志里找IP
+    let _mdns = Mdns::start(wifi.hostname(), 80, &["led", "effects", "scenes", "wifi"])?;
+
+    // Wi-Fi连接后通过SNTP同步时间，服务器和时区保存在NVS中，通过 /clock 修改
+    let clock = Clock::new();
+    let clock_store = Arc::new(Mutex::new(ClockStore::new(nvs.clone())?));
+    {
+        let clock = clock.clone();
+        let clock_store = clock_store.clone();
+        let mut wifi_state = wifi.subscribe();
+        std::thread::spawn(move || {
+            wifi_state.wait_for(WifiState::is_connected, None);
+            // 等待连接期间可能已经通过 /clock 修改并启动过了，以最新的配置为准
+            if clock.sntp_running() {
+                return;
+            }
+            let result = clock_store
+                .lock()
+                .unwrap()
+                .settings()
+                .and_then(|settings| clock.start_sntp(&settings));
+            if let Err(e) = result {
+                log::error!("Failed to start SNTP: {}", e);
+            }
+        });
+    }
+
+    // 记录连接状态的变化，HTTP服务器监听所有地址，重连后无需重启，在热点上也可以访问
+    let mut wifi_state = wifi.subscribe();
+    std::thread::spawn(move || {
+        loop {
+            log::info!("Wi-Fi state: {:?}", wifi_state.changed());
+        }
+    });
 
     // 初始化HTTP服务器
     let mut server = esp_idf_svc::http::server::EspHttpServer::new(
@@ -43,11 +106,37 @@ fn main() -> anyhow::Result<()> {
         })
     )?;
 
-    // 初始化LED控制器
+    // 初始化LED控制器，并交给灯效线程驱动
     let led = peripherals.pins.gpio8;
     let channel = peripherals.rmt.channel0;
-    let ws2812_rmt = Arc::new(Mutex::new(WS2812RMT::new(led, channel)?));
-    let ws2812_rmt_clone = ws2812_rmt.clone();
+    let effects = EffectRunner::spawn(WS2812RMT::new(led, channel)?, 30)?;
+    let effects_color = effects.clone();
+    let effects_shutdown = effects.clone();
+    let effects_brightness = effects.clone();
+    let effects_segments = effects.clone();
+    let effects_text = effects.clone();
+    let effects_save = effects.clone();
+    let effects_recall = effects.clone();
+
+    // 恢复上一次召回的场景，直接调色或切换灯效不会被记住
+    if let Some(scene) = scenes.lock().unwrap().last()? {
+        log::info!("Restore scene {:?}", scene);
+        effects.apply_scene(&scene)?;
+    }
+    let scenes_list = scenes.clone();
+    let scenes_save = scenes.clone();
+    let scenes_recall = scenes.clone();
+
+    // 按本地时间触发灯光动作，规则保存在NVS中，通过 /schedule 修改
+    let schedule_store = Arc::new(Mutex::new(ScheduleStore::new(nvs.clone())?));
+    let scheduler = {
+        let effects = effects.clone();
+        let scenes = scenes.clone();
+        let schedule = schedule_store.lock().unwrap().schedule()?;
+        Scheduler::spawn(clock.clone(), schedule, move |rule: &Rule| {
+            rule.action.apply(&effects, |name| scenes.lock().unwrap().load(name))
+        })?
+    };
 
     server.fn_handler("/", Method::Get, |req| {
         let mut response = req.into_ok_response()?;
@@ -57,40 +146,230 @@ fn main() -> anyhow::Result<()> {
 
     // 注册处理设置LED颜色的HTTP请求的函数
     server.fn_handler("/set-color", Method::Post, move |mut req| {
-        // 从请求中获取JSON数据并解析为颜色参数
-        let params: Params = get_json_body(&mut req)?;
-        let color = params.color;
+        // 从请求中获取JSON数据并解析为颜色参数，颜色可以是RGB、HSV、HSL或色温
+        let request: ColorRequest = get_json_body(&mut req)?;
         // 设置LED颜色
-        ws2812_rmt_clone.lock().unwrap().set_pixel(RGB8::new(color.r, color.g, color.b))?;
-        log::info!("color: {:?}", color);
+        request.apply(&effects_color)?;
+        log::info!("color: {:?}", request.color);
 
         // 构建并返回成功的HTTP响应
-        let mut response = req.into_response(
-            200,
-            Some("ok"),
-            &[
-                ("Access-Control-Allow-Origin", "*"),
-                ("Access-Control-Allow-Methods", "all"),
-            ]
-        )?;
-        response.write_all(b"OK")?;
-        Ok::<(), anyhow::Error>(())
+        ok_response(req)
     })?;
 
     // 注册处理关闭LED的HTTP请求的函数
     server.fn_handler("/shutdown", Method::Get, move |req| {
-        // 关闭LED控制器
-        ws2812_rmt.lock().unwrap().shutdown()?;
-        let mut response = req.into_response(
-            200,
-            Some("ok"),
-            &[
-                ("Access-Control-Allow-Origin", "*"),
-                ("Access-Control-Allow-Methods", "all"),
-            ]
-        )?;
-        response.write_all(b"OK")?;
-        Ok::<(), anyhow::Error>(())
+        // 关闭LED
+        effects_shutdown.off()?;
+        ok_response(req)
+    })?;
+
+    // 注册切换灯效的HTTP请求的函数
+    server.fn_handler("/effect", Method::Post, move |mut req| {
+        // 请求体为灯效配置，例如 {"effect":"rainbow","cycle_ms":5000}，可以用segment指定分段
+        let request: EffectRequest = get_json_body(&mut req)?;
+        request.apply(&effects)?;
+        ok_response(req)
+    })?;
+
+    // 注册设置亮度的HTTP请求的函数，例如 {"brightness":128} 或 {"segment":"left","brightness":64}
+    server.fn_handler("/brightness", Method::Post, move |mut req| {
+        let request: BrightnessRequest = get_json_body(&mut req)?;
+        request.apply(&effects_brightness)?;
+        ok_response(req)
+    })?;
+
+    // 注册划分分段的HTTP请求的函数，例如 [{"name":"left","start":0,"len":30}]
+    server.fn_handler("/segments", Method::Post, move |mut req| {
+        let segments: Vec<Segment> = get_json_body(&mut req)?;
+        effects_segments.set_segments(segments)?;
+        ok_response(req)
+    })?;
+
+    // 注册在点阵上滚动显示文字的HTTP请求的函数，例如 {"text":"192.168.1.10","speed":12}
+    server.fn_handler("/text", Method::Post, move |mut req| {
+        let marquee: Marquee = get_json_body(&mut req)?;
+        effects_text.set_effect(EffectConfig::Marquee(marquee))?;
+        ok_response(req)
+    })?;
+
+    // 注册列出已保存场景的HTTP请求的函数，返回场景名的JSON数组
+    server.fn_handler("/scenes", Method::Get, move |req| {
+        let names = scenes_list.lock().unwrap().list()?;
+        json_response(req, &names)
+    })?;
+
+    // 注册保存场景的HTTP请求的函数，例如 {"name":"reading"}
+    // 不带scene时保存当前的灯效和亮度
+    server.fn_handler("/scenes", Method::Post, move |mut req| {
+        let params: SceneParams = get_json_body(&mut req)?;
+        let scene = params.scene.unwrap_or_else(|| effects_save.scene());
+        scenes_save.lock().unwrap().save(&params.name, &scene)?;
+        ok_response(req)
+    })?;
+
+    // 注册恢复场景的HTTP请求的函数，恢复的场景会在下次启动时自动恢复
+    server.fn_handler("/scenes/recall", Method::Post, move |mut req| {
+        let params: SceneName = get_json_body(&mut req)?;
+        let mut store = scenes_recall.lock().unwrap();
+        let scene = store
+            .load(&params.name)?
+            .ok_or_else(|| anyhow!("scene {} not found", params.name))?;
+        effects_recall.apply_scene(&scene)?;
+        store.set_last(&scene)?;
+        ok_response(req)
+    })?;
+
+    // 注册删除场景的HTTP请求的函数
+    server.fn_handler("/scenes/delete", Method::Post, move |mut req| {
+        let params: SceneName = get_json_body(&mut req)?;
+        scenes.lock().unwrap().delete(&params.name)?;
+        ok_response(req)
+    })?;
+
+    // 注册列出已知WiFi网络的HTTP请求的函数，只返回SSID，按优先级从高到低
+    let networks_list = networks.clone();
+    server.fn_handler("/wifi/networks", Method::Get, move |req| {
+        let ssids = networks_list
+            .lock()
+            .unwrap()
+            .list()?
+            .into_iter()
+            .map(|n| n.ssid)
+            .collect::<Vec<_>>();
+        json_response(req, &ssids)
+    })?;
+
+    // 注册添加已知WiFi网络的HTTP请求的函数，例如 {"ssid":"lab","password":"secret"}
+    let networks_add = networks.clone();
+    let wifi_add = wifi.clone();
+    server.fn_handler("/wifi/networks", Method::Post, move |mut req| {
+        let network: KnownNetwork = get_json_body(&mut req)?;
+        let mut store = networks_add.lock().unwrap();
+        store.add(network)?;
+        wifi_add.set_networks(store.list()?)?;
+        ok_response(req)
+    })?;
+
+    // 注册扫描周围AP的HTTP请求的函数，返回SSID、BSSID、信道、信号强度和认证方式
+    let wifi_scan = wifi.clone();
+    server.fn_handler("/wifi/scan", Method::Get, move |req| {
+        let results = scan(&mut wifi_scan.wifi())?;
+        json_response(req, &results)
+    })?;
+
+    // 注册读取连接诊断信息的HTTP请求的函数
+    let wifi_diagnostics = wifi.clone();
+    server.fn_handler("/wifi/diagnostics", Method::Get, move |req| {
+        json_response(req, &wifi_diagnostics.diagnostics())
+    })?;
+
+    // 注册读取时间的HTTP请求的函数，返回是否已同步、本地时间和同步配置
+    let clock_status = clock.clone();
+    let clock_store_get = clock_store.clone();
+    server.fn_handler("/clock", Method::Get, move |req| {
+        let status = ClockStatus {
+            synced: clock_status.is_synced(),
+            local: clock_status.local_now(),
+            settings: clock_store_get.lock().unwrap().settings()?,
+        };
+        json_response(req, &status)
+    })?;
+
+    // 注册修改时间同步配置的HTTP请求的函数，立即生效，例如 {"servers":["pool.ntp.org"],"timezone":"UTC0"}
+    let clock_set = clock.clone();
+    server.fn_handler("/clock", Method::Post, move |mut req| {
+        let settings: ClockSettings = get_json_body(&mut req)?;
+        clock_store.lock().unwrap().set_settings(&settings)?;
+        clock_set.start_sntp(&settings)?;
+        ok_response(req)
+    })?;
+
+    // 注册读取定时规则的HTTP请求的函数，同时返回今天的日出日落时间
+    let scheduler_get = scheduler.clone();
+    let clock_schedule = clock.clone();
+    server.fn_handler("/schedule", Method::Get, move |req| {
+        let schedule = scheduler_get.schedule();
+        let status = ScheduleStatus {
+            sun: clock_schedule.local_now().and_then(|now| schedule.sun_times(&now)),
+            schedule,
+        };
+        json_response(req, &status)
+    })?;
+
+    // 注册添加定时规则的HTTP请求的函数，同名规则会被替换
+    // 例如 {"name":"dusk","trigger":{"type":"sunset"},"action":{"type":"color","color":{"kelvin":2700},"brightness":102}}
+    let scheduler_add = scheduler.clone();
+    let schedule_store_add = schedule_store.clone();
+    server.fn_handler("/schedule", Method::Post, move |mut req| {
+        let rule: Rule = get_json_body(&mut req)?;
+        update_schedule(&schedule_store_add, &scheduler_add, |schedule| schedule.upsert(rule))?;
+        ok_response(req)
+    })?;
+
+    // 注册删除定时规则的HTTP请求的函数，例如 {"name":"dusk"}
+    let scheduler_delete = scheduler.clone();
+    let schedule_store_delete = schedule_store.clone();
+    server.fn_handler("/schedule/delete", Method::Post, move |mut req| {
+        let params: RuleName = get_json_body(&mut req)?;
+        update_schedule(&schedule_store_delete, &scheduler_delete, |schedule| {
+            if !schedule.remove(&params.name) {
+                return Err(anyhow!("rule {} not found", params.name));
+            }
+            Ok(())
+        })?;
+        ok_response(req)
+    })?;
+
+    // 注册设置位置的HTTP请求的函数，用于本地计算日出日落，例如 {"latitude":39.9,"longitude":116.4}
+    server.fn_handler("/schedule/location", Method::Post, move |mut req| {
+        let location: Location = get_json_body(&mut req)?;
+        update_schedule(&schedule_store, &scheduler, |schedule| {
+            schedule.location = Some(location);
+            Ok(())
+        })?;
+        ok_response(req)
+    })?;
+
+    // 注册读取站点接口IP配置的HTTP请求的函数
+    let networks_ip = networks.clone();
+    server.fn_handler("/wifi/ip", Method::Get, move |req| {
+        let settings = networks_ip.lock().unwrap().ip_settings()?;
+        json_response(req, &settings)
+    })?;
+
+    // 注册修改站点接口IP配置的HTTP请求的函数，重启后生效
+    // 例如 {"hostname":"lab-{mac}","static_ip":{"ip":"10.0.0.20","netmask":"255.255.255.0","gateway":"10.0.0.1","dns":["10.0.0.1"]}}
+    let networks_set_ip = networks.clone();
+    server.fn_handler("/wifi/ip", Method::Post, move |mut req| {
+        let settings: IpSettings = get_json_body(&mut req)?;
+        networks_set_ip.lock().unwrap().set_ip_settings(&settings)?;
+        ok_response(req)
+    })?;
+
+    // 注册读取本地热点配置的HTTP请求的函数，不返回密码
+    let networks_ap = networks.clone();
+    server.fn_handler("/wifi/ap", Method::Get, move |req| {
+        let mut settings = networks_ap.lock().unwrap().ap_settings()?;
+        settings.password.clear();
+        json_response(req, &settings)
+    })?;
+
+    // 注册修改本地热点配置的HTTP请求的函数，重启后生效，例如 {"enabled":true,"ssid":"lab-{mac}","password":"12345678"}
+    let networks_set_ap = networks.clone();
+    server.fn_handler("/wifi/ap", Method::Post, move |mut req| {
+        let settings: ApSettings = get_json_body(&mut req)?;
+        networks_set_ap.lock().unwrap().set_ap_settings(&settings)?;
+        ok_response(req)
+    })?;
+
+    // 注册删除已知WiFi网络的HTTP请求的函数，例如 {"ssid":"lab"}
+    let wifi_remove = wifi.clone();
+    server.fn_handler("/wifi/networks/delete", Method::Post, move |mut req| {
+        let params: SsidParams = get_json_body(&mut req)?;
+        let mut store = networks.lock().unwrap();
+        store.remove(&params.ssid)?;
+        wifi_remove.set_networks(store.list()?)?;
+        ok_response(req)
     })?;
 
     // 保持程序运行
@@ -100,25 +379,92 @@ fn main() -> anyhow::Result<()> {
     }
 }
 
-// 表示RGB颜色的结构体
+// 保存场景的参数
 #[derive(Debug, Serialize, Deserialize)]
-struct Color {
-    r: u8,
-    g: u8,
-    b: u8,
+struct SceneParams {
+    name: String,
+    #[serde(default)]
+    scene: Option<Scene>,
 }
 
-// 包含颜色参数的结构体
+// 只包含场景名的参数
 #[derive(Debug, Serialize, Deserialize)]
-struct Params {
-    color: Color,
+struct SceneName {
+    name: String,
 }
 
-// 从Color结构体到RGB8的转换
-impl From<Color> for RGB8 {
-    fn from(color: Color) -> Self {
-        RGB8::new(color.r, color.g, color.b)
-    }
+// 时间的状态
+#[derive(Debug, Serialize)]
+struct ClockStatus {
+    synced: bool,
+    local: Option<LocalTime>,
+    settings: ClockSettings,
+}
+
+// 定时规则和今天的日出日落时间
+#[derive(Debug, Serialize)]
+struct ScheduleStatus {
+    #[serde(flatten)]
+    schedule: Schedule,
+    sun: Option<SunTimes>,
+}
+
+// 只包含规则名的参数
+#[derive(Debug, Serialize, Deserialize)]
+struct RuleName {
+    name: String,
+}
+
+// 只包含SSID的参数
+#[derive(Debug, Serialize, Deserialize)]
+struct SsidParams {
+    ssid: String,
+}
+
+// 修改定时规则，检查通过后先保存到NVS再交给定时任务线程
+fn update_schedule(
+    store: &Mutex<ScheduleStore>,
+    scheduler: &Scheduler,
+    update: impl FnOnce(&mut Schedule) -> anyhow::Result<()>
+) -> anyhow::Result<()> {
+    // 持有存储的锁，两个请求同时修改时不会丢失其中一个
+    let mut store = store.lock().unwrap();
+    let mut schedule = scheduler.schedule();
+    update(&mut schedule)?;
+    store.set_schedule(&schedule)?;
+    scheduler.set_schedule(schedule);
+    Ok(())
+}
+
+// 返回成功的HTTP响应
+fn ok_response(req: Request<&mut EspHttpConnection<'_>>) -> anyhow::Result<()> {
+    let mut response = req.into_response(
+        200,
+        Some("ok"),
+        &[
+            ("Access-Control-Allow-Origin", "*"),
+            ("Access-Control-Allow-Methods", "all"),
+        ]
+    )?;
+    response.write_all(b"OK")?;
+    Ok(())
+}
+
+// 返回JSON格式的HTTP响应
+fn json_response<T: Serialize>(
+    req: Request<&mut EspHttpConnection<'_>>,
+    value: &T
+) -> anyhow::Result<()> {
+    let mut response = req.into_response(
+        200,
+        Some("ok"),
+        &[
+            ("Access-Control-Allow-Origin", "*"),
+            ("Content-Type", "application/json"),
+        ]
+    )?;
+    response.write_all(&serde_json::to_vec(value)?)?;
+    Ok(())
 }
 
 // 从HTTP请求中获取JSON负载的函数
@@ -10,20 +10,23 @@ use esp_idf_svc::hal::{
     task::notification::Notification,
 };
 // 导入项目中用于控制 WS2812 LED 的模块
-use rust_embedded_study::led::WS2812RMT;
+use rust_embedded_study::led::{ effect::{ EffectConfig, EffectRunner }, WS2812RMT };
 
 // 主函数，返回一个 Result 类型以处理可能的错误
 fn main() -> anyhow::Result<()> {
     // 初始化系统和外设
     let (_sys, peripherals, _nvs) = rust_embedded_study::init()?;
 
-    // 创建 WS2812 LED 驱动实例
-    let mut led = WS2812RMT::new(peripherals.pins.gpio8, peripherals.rmt.channel0)?;
+    // 创建 WS2812 LED 驱动实例，并交给灯效线程驱动
+    let led = EffectRunner::spawn(
+        WS2812RMT::new(peripherals.pins.gpio8, peripherals.rmt.channel0)?,
+        30
+    )?;
     // 创建按钮输入引脚驱动实例
     let mut button = PinDriver::input(peripherals.pins.gpio9)?;
 
     // 创建一个无缓冲的通道，用于在线程间传输数据
-    let (tx, rx) = channel::<bool>();
+    let (tx, rx) = channel::<()>();
     // 配置按钮引脚为上拉，并设置中断类型为上升沿触发
     button.set_pull(Pull::Up)?;
     button.set_interrupt_type(InterruptType::PosEdge)?;
@@ -33,30 +36,30 @@ fn main() -> anyhow::Result<()> {
         // 创建一个通知对象，用于在中断发生时通知其他线程
         let notification = Arc::new(Notification::new());
         let notifier = notification.notifier();
-        // 定义一个变量来表示 LED 的状态（开/关）
-        let mut open = false;
         // 使用 unsafe 块来调用可能不安全的中断订阅函数
         unsafe {
             button.subscribe(move || {
                 notifier.notify_and_yield(NonZeroU32::new(1).unwrap());
             })?;
         }
-        // 无限循环，等待中断发生并切换 LED 状态
+        // 无限循环，等待中断发生并通知主线程
         loop {
             button.enable_interrupt()?;
             notification.wait(esp_idf_svc::hal::delay::BLOCK);
-            open = !open;
-            tx.send(open)?;
+            tx.send(())?;
         }
     });
 
-    // 主线程循环接收 LED 状态，并根据状态设置 LED 颜色
-    while let Ok(open) = rx.recv() {
-        if open {
-            led.set_pixel(rust_embedded_study::led::RGB8::new(255, 255, 0))?;
+    // 每按一次按钮切换到下一个内置灯效，最后一次按下关灯
+    let presets = EffectConfig::presets();
+    let mut index = 0;
+    while let Ok(()) = rx.recv() {
+        if index < presets.len() {
+            led.set_effect(presets[index].clone())?;
         } else {
-            led.set_pixel(rust_embedded_study::led::RGB8::new(0, 0, 0))?;
+            led.off()?;
         }
+        index = (index + 1) % (presets.len() + 1);
     }
 
     Ok(())
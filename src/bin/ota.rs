@@ -1,4 +1,4 @@
-use std::sync::{ mpsc::{ channel, Sender }, Arc, Condvar, Mutex };
+use std::{ sync::{ mpsc::{ channel, Sender }, Arc, Condvar, Mutex }, time::Duration };
 
 use anyhow::anyhow;
 use embedded_svc::http::{ client::Client, Headers };
@@ -7,6 +7,13 @@ use esp_idf_svc::{
     http::{ client::{ Configuration, EspHttpConnection }, Method },
     ota::{ EspOta, FirmwareInfo },
 };
+use rust_embedded_study::wifi::{
+    mdns::{ Mdns, OTA_SERVICE },
+    networks::NetworkStore,
+    portal,
+    WifiManager,
+    WifiState,
+};
 
 // 配置结构体，用于读取配置文件
 #[toml_cfg::toml_config]
@@ -23,20 +30,25 @@ pub struct Config {
 const FIRMWARE_DOWNLOAD_CHUNK_SIZE: usize = 1024 * 20;
 const FIRMWARE_MAX_SIZE: usize = 1024 * 1024;
 const FIRMWARE_MIN_SIZE: usize = size_of::<FirmwareInfo>() + 1024;
+// OTA服务器没有在TXT记录中指定path时使用的固件路径
+const FIRMWARE_DEFAULT_PATH: &str = "/ble_server.bin";
 
 // 主函数，程序的入口点
 fn main() -> anyhow::Result<()> {
     // 初始化系统循环、外设和NVS闪存
-    let (sysloop, peripherals, nvs) = rust_embedded_study::init()?;
-
-    // 连接WiFi
-    let _wifi = rust_embedded_study::wifi::connect_wifi(
-        CONFIG.wifi_ssid,
-        &CONFIG.wifi_psk,
-        peripherals.modem,
-        sysloop,
-        nvs
-    )?;
+    let (sysloop, mut peripherals, nvs) = rust_embedded_study::init()?;
+
+    // 连接WiFi，依次尝试NVS中保存的已知网络，断开后自动重连
+    let mut networks = NetworkStore::new(nvs.clone())?;
+    let known = networks.list_or_seed(CONFIG.wifi_ssid, CONFIG.wifi_psk)?;
+    // 没有已知网络或者启动时按住按钮，进入配网模式
+    if known.is_empty() || portal::button_held(&mut peripherals.pins.gpio9)? {
+        return portal::run(peripherals.modem, sysloop, nvs);
+    }
+    let wifi = WifiManager::start(peripherals.modem, sysloop, nvs, known, &networks.ip_settings()?)?;
+    let mut wifi_state = wifi.subscribe();
+    // 通过mDNS查找OTA服务器，服务器宣告 _rustled-ota._tcp 服务即可，不用写死IP
+    let mdns = Mdns::browser(wifi.hostname())?;
 
     // 初始化BLE设备和广告
     let device = esp32_nimble::BLEDevice::take();
@@ -99,8 +111,12 @@ fn main() -> anyhow::Result<()> {
         while !*is_start {
             is_start = condvar.wait(is_start).unwrap();
         }
-        log::warn!("Start OTA");
-        firmware("http://192.168.88.235:5500/ble_server.bin", tx)
+        drop(is_start);
+        // 下载固件前等待网络恢复
+        wifi_state.wait_for(WifiState::is_connected, None);
+        let uri = find_ota_server(&mdns);
+        log::warn!("Start OTA from {}", uri);
+        firmware(&uri, tx)
     });
 
     // 配置广告数据并启动广告
@@ -127,6 +143,22 @@ fn main() -> anyhow::Result<()> {
     Ok(()) 
 }
 
+// 查找OTA服务器，没有找到时每隔几秒重试
+fn find_ota_server(mdns: &Mdns) -> String {
+    loop {
+        match mdns.find(OTA_SERVICE, "_tcp", Duration::from_secs(3)) {
+            Ok(Some(server)) => {
+                if let Some(uri) = server.url(FIRMWARE_DEFAULT_PATH) {
+                    return uri;
+                }
+            }
+            Ok(None) => log::warn!("OTA server not found, retrying"),
+            Err(e) => log::error!("mDNS query failed: {}", e),
+        }
+        std::thread::sleep(Duration::from_secs(5));
+    }
+}
+
 // 固件下载和更新函数
 fn firmware(uri: &str, tx: Sender<(usize, usize)>) -> anyhow::Result<()> {
     // 初始化HTTP客户端
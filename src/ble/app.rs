@@ -1,15 +1,19 @@
 use anyhow::{ anyhow, bail };
+use enumset::enum_set;
 use esp_idf_svc::bt::{
     ble::{
         gap::{ AdvConfiguration, BleGapEvent },
         gatt::{
             server::{ ConnectionId, GattsEvent, TransferId },
+            GattCharacteristic,
             GattConnParams,
+            GattDescriptor,
             GattInterface,
             GattResponse,
             GattServiceId,
             GattStatus,
             Handle,
+            Permission,
         },
     },
     BdAddr,
@@ -17,19 +21,43 @@ use esp_idf_svc::bt::{
     BtUuid,
 };
 use std::{
-    collections::{ HashMap, HashSet },
+    collections::{ HashMap, HashSet, VecDeque },
     fmt::Debug,
     hash::Hash,
     ops::Deref,
-    sync::{ Arc, Condvar, Mutex },
+    sync::{ Arc, Mutex },
+};
+use super::{
+    app_builder::BLEAppBuilder,
+    AdvertisingPolicy,
+    ExEspBleGap,
+    ExEspGatts,
+    NotifyExt,
+    ReadExt,
+    Service,
+    Subscription,
+    UserState,
+    WriteExt,
+    CCCD_UUID,
 };
-use super::{ app_builder::BLEAppBuilder, ExEspBleGap, ExEspGatts, ReadExt, Service, WriteExt };
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Connection {
     pub peer: BdAddr,
     pub conn_id: ConnectionId,
     pub mtu: Option<u16>,
+    /// 这个连接订阅的特征
+    pub subscriptions: HashMap<HashBtUuid, Subscription>,
+    /// 用户状态，连接断开时随连接一起丢弃
+    pub user_state: UserState,
+}
+
+/// 等待添加的属性，同一个服务中的特征和描述符必须逐个添加，描述符才会属于前面的特征
+#[derive(Debug, Clone)]
+pub enum PendingAttribute {
+    Characteristic(GattCharacteristic),
+    /// 描述符和它所属特征的UUID
+    Descriptor(HashBtUuid, GattDescriptor),
 }
 
 #[derive(Debug, Clone, Default)]
@@ -38,7 +66,23 @@ pub struct ConnectedState {
     pub gatt_if: Option<GattInterface>,
     pub service_handle_map: HashMap<Handle, HashBtUuid>,
     pub attr_handle_map: HashMap<Handle, HashBtUuid>,
+    /// CCCD的句柄到所属特征的映射
+    pub cccd_handle_map: HashMap<Handle, HashBtUuid>,
+    pub pending_attributes: HashMap<Handle, VecDeque<PendingAttribute>>,
+    /// 已经发出、还在等待确认的通知或指示的对端
     pub notify_confirmed: Option<BdAddr>,
+    /// 等待发送的通知和指示，上一条确认后再发送下一条
+    pub notify_queue: VecDeque<PendingNotification>,
+}
+
+/// 等待发送的通知或指示
+#[derive(Debug, Clone)]
+pub struct PendingNotification {
+    pub conn_id: ConnectionId,
+    pub peer: BdAddr,
+    pub attr_handle: Handle,
+    pub subscription: Subscription,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -75,14 +119,15 @@ pub struct BLEApp<'a, State: Sync + Send + Clone = ()> {
     pub app_id: u16,
     pub device_name: Option<&'a str>,
     pub adv_configuration: AdvConfiguration<'a>,
+    pub advertising_policy: AdvertisingPolicy,
     pub gap: ExEspBleGap<'a>,
     pub gatts: ExEspGatts<'a>,
     pub state: State,
     pub services: HashMap<HashBtUuid, Service<State>>,
     pub read_characteristics: HashMap<HashBtUuid, Arc<dyn ReadExt<State = State>>>,
     pub write_characteristics: HashMap<HashBtUuid, Arc<dyn WriteExt<State = State>>>,
+    pub notify_characteristics: HashMap<HashBtUuid, Arc<dyn NotifyExt<State = State>>>,
     pub connected_state: Arc<Mutex<ConnectedState>>,
-    condvar: Arc<Condvar>,
 }
 
 impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
@@ -96,6 +141,7 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
         gap: ExEspBleGap<'a>,
         gatts: ExEspGatts<'a>,
         adv_configuration: AdvConfiguration<'a>,
+        advertising_policy: AdvertisingPolicy,
         device_name: Option<&'a str>
     ) -> Self {
         Self {
@@ -103,13 +149,14 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
             services: HashMap::new(),
             read_characteristics: HashMap::new(),
             write_characteristics: HashMap::new(),
+            notify_characteristics: HashMap::new(),
             state,
             gap,
             gatts,
             connected_state: Arc::new(Mutex::new(ConnectedState::default())),
             adv_configuration,
+            advertising_policy,
             device_name,
-            condvar: Arc::new(Condvar::new()),
         }
     }
 
@@ -122,6 +169,10 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
             let characteristic = i.characteristic();
             self.write_characteristics.insert(characteristic.uuid.into(), i.clone());
         });
+        service.notify_characteristics.iter().for_each(|i| {
+            let characteristic = i.characteristic();
+            self.notify_characteristics.insert(characteristic.uuid.into(), i.clone());
+        });
         self.services.insert(service.service_id.id.uuid.clone().into(), service);
     }
 
@@ -151,23 +202,68 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
 
         let mut connected_state = self.connected_state.lock().unwrap();
         connected_state.service_handle_map.insert(service_handle, hash_bt_uuid);
-        // 添加特征
+        // 按顺序排好特征和它们的描述符，同时读写和通知的特征只添加一次
         let mut uuids: HashSet<HashBtUuid> = HashSet::new();
-
-        for i in &service.write_characteristics {
-            let characteristic = i.characteristic();
-            if uuids.insert(i.characteristic().uuid.into()) {
-                self.gatts.add_characteristic(service_handle, &characteristic, &[])?;
+        let mut pending = VecDeque::new();
+        let characteristics = service.write_characteristics
+            .iter()
+            .map(|i| i.characteristic())
+            .chain(service.read_characteristics.iter().map(|i| i.characteristic()))
+            .chain(service.notify_characteristics.iter().map(|i| i.characteristic()));
+
+        for characteristic in characteristics {
+            let uuid: HashBtUuid = characteristic.uuid.clone().into();
+            if uuids.insert(uuid.clone()) {
+                let descriptors = self.descriptors(&uuid);
+                pending.push_back(PendingAttribute::Characteristic(characteristic));
+                for descriptor in descriptors {
+                    pending.push_back(PendingAttribute::Descriptor(uuid.clone(), descriptor));
+                }
             }
         }
+        connected_state.pending_attributes.insert(service_handle, pending);
+        self.add_next_attribute(&connected_state, service_handle)
+    }
 
-        for i in &service.read_characteristics {
-            let characteristic = i.characteristic();
-            if uuids.insert(i.characteristic().uuid.into()) {
-                self.gatts.add_characteristic(service_handle, &characteristic, &[])?;
-            }
+    /// 特征的描述符，通知特征没有声明CCCD时自动加上
+    fn descriptors(&self, uuid: &HashBtUuid) -> Vec<GattDescriptor> {
+        let mut descriptors = if let Some(c) = self.read_characteristics.get(uuid) {
+            c.descriptors()
+        } else if let Some(c) = self.write_characteristics.get(uuid) {
+            c.descriptors()
+        } else if let Some(c) = self.notify_characteristics.get(uuid) {
+            c.descriptors()
+        } else {
+            vec![]
+        };
+        let cccd = BtUuid::uuid16(CCCD_UUID);
+        if self.notify_characteristics.contains_key(uuid) && !descriptors.iter().any(|d| d.uuid == cccd) {
+            descriptors.push(GattDescriptor {
+                uuid: cccd,
+                permissions: enum_set!(Permission::Read | Permission::Write),
+            });
         }
+        descriptors
+    }
 
+    /// 添加服务中下一个等待添加的属性，添加完成后会触发CharacteristicAdded或DescriptorAdded事件
+    fn add_next_attribute(
+        &self,
+        connected_state: &ConnectedState,
+        service_handle: Handle
+    ) -> anyhow::Result<()> {
+        let next = connected_state.pending_attributes
+            .get(&service_handle)
+            .and_then(|pending| pending.front());
+        match next {
+            Some(PendingAttribute::Characteristic(characteristic)) => {
+                self.gatts.add_characteristic(service_handle, characteristic, &[])?;
+            }
+            Some(PendingAttribute::Descriptor(_, descriptor)) => {
+                self.gatts.add_descriptor(service_handle, descriptor)?;
+            }
+            None => {}
+        }
         Ok(())
     }
 
@@ -179,44 +275,117 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
         char_uuid: BtUuid
     ) -> anyhow::Result<()> {
         let hash_uuid: HashBtUuid = char_uuid.into();
+        let mut connected_state = self.connected_state.lock().unwrap();
+        match connected_state.pending_attributes.get_mut(&service_handle).and_then(|p| p.pop_front()) {
+            Some(PendingAttribute::Characteristic(c)) if c.uuid == *hash_uuid => {}
+            other => bail!("Unexpected characteristic {:?}, pending {:?}", hash_uuid, other),
+        }
+        connected_state.attr_handle_map.insert(attr_handle, hash_uuid);
+        self.add_next_attribute(&connected_state, service_handle)
+    }
 
-        let descriptors = if let Some(c) = self.read_characteristics.get(&hash_uuid) {
-            c.descriptors()
-        } else if let Some(c) = self.write_characteristics.get(&hash_uuid) {
-            c.descriptors()
-        } else {
-            bail!("No characteristic found for uuid {:?}", hash_uuid)
-        };
+    // 在添加描述符完成后，记录CCCD属于哪个特征
+    fn on_descriptor_added(
+        &self,
+        attr_handle: Handle,
+        service_handle: Handle,
+        descr_uuid: BtUuid
+    ) -> anyhow::Result<()> {
         let mut connected_state = self.connected_state.lock().unwrap();
-        connected_state.attr_handle_map.insert(attr_handle, hash_uuid.clone());
-        for i in descriptors {
-            self.gatts.add_descriptor(service_handle, &i)?;
+        let owner = match
+            connected_state.pending_attributes.get_mut(&service_handle).and_then(|p| p.pop_front())
+        {
+            Some(PendingAttribute::Descriptor(owner, d)) if d.uuid == descr_uuid => owner,
+            other => bail!("Unexpected descriptor {:?}, pending {:?}", descr_uuid, other),
+        };
+        if descr_uuid == BtUuid::uuid16(CCCD_UUID) {
+            connected_state.cccd_handle_map.insert(attr_handle, owner);
         }
-        Ok(())
+        self.add_next_attribute(&connected_state, service_handle)
     }
 
-    fn on_write(&self, attr_handle: Handle, value: &[u8]) -> anyhow::Result<()> {
+    fn on_write(&self, conn_id: ConnectionId, attr_handle: Handle, value: &[u8]) -> anyhow::Result<()> {
         let connect_state = self.connected_state.lock().unwrap();
-        if let Some(uuid) = connect_state.attr_handle_map.get(&attr_handle) {
-            let Some(characteristic) = self.write_characteristics.get(uuid) else {
+        if let Some(uuid) = connect_state.cccd_handle_map.get(&attr_handle).cloned() {
+            // 回调中可能会访问连接状态，先释放锁
+            drop(connect_state);
+            return self.on_cccd_write(conn_id, uuid, value);
+        }
+        if let Some(uuid) = connect_state.attr_handle_map.get(&attr_handle).cloned() {
+            drop(connect_state);
+            let Some(characteristic) = self.write_characteristics.get(&uuid) else {
                 bail!("characteristic not found")
             };
 
-            characteristic.on_write(self.state.clone(), value)?;
+            characteristic.on_write(self.state.clone(), &self.connection(conn_id)?, value)?;
         }
 
         Ok(())
     }
 
-    fn on_read(&self, attr_handle: Handle) -> anyhow::Result<&[u8]> {
+    /// 客户端写入CCCD，记录这个连接的订阅，订阅状态变化时调用回调
+    fn on_cccd_write(
+        &self,
+        conn_id: ConnectionId,
+        uuid: HashBtUuid,
+        value: &[u8]
+    ) -> anyhow::Result<()> {
+        let subscription = Subscription::from_cccd(value);
+        let (previous, connection) = {
+            let mut connected_state = self.connected_state.lock().unwrap();
+            let connection = connected_state.connections
+                .iter_mut()
+                .find(|c| c.conn_id == conn_id)
+                .ok_or(anyhow!("connection {} not found", conn_id))?;
+            let previous = match subscription {
+                Some(subscription) => connection.subscriptions.insert(uuid.clone(), subscription),
+                None => connection.subscriptions.remove(&uuid),
+            };
+            (previous, connection.clone())
+        };
+        let Some(characteristic) = self.notify_characteristics.get(&uuid) else {
+            bail!("characteristic not found")
+        };
+        match (previous, subscription) {
+            (None, Some(_)) => characteristic.on_subscribe(self.state.clone(), &connection),
+            (Some(_), None) => characteristic.on_unsubscribe(self.state.clone(), &connection),
+            _ => Ok(()),
+        }
+    }
+
+    /// 读取CCCD时返回这个连接的订阅状态，不是CCCD时返回`None`
+    fn cccd_value(&self, conn_id: ConnectionId, attr_handle: Handle) -> Option<[u8; 2]> {
         let connected_state = self.connected_state.lock().unwrap();
-        let uuid = connected_state.attr_handle_map
-            .get(&attr_handle)
+        let uuid = connected_state.cccd_handle_map.get(&attr_handle)?;
+        let subscription = connected_state.connections
+            .iter()
+            .find(|c| c.conn_id == conn_id)
+            .and_then(|c| c.subscriptions.get(uuid).copied());
+        Some(Subscription::to_cccd(subscription))
+    }
+
+    fn on_read(&self, conn_id: ConnectionId, attr_handle: Handle) -> anyhow::Result<&[u8]> {
+        let uuid = self.connected_state
+            .lock()
+            .unwrap()
+            .attr_handle_map.get(&attr_handle)
+            .cloned()
             .ok_or(anyhow!("attr_handle not found"))?;
-        let Some(characteristic) = self.read_characteristics.get(uuid) else {
+        let Some(characteristic) = self.read_characteristics.get(&uuid) else {
             bail!("characteristic not found")
         };
-        characteristic.on_read(self.state.clone())
+        characteristic.on_read(self.state.clone(), &self.connection(conn_id)?)
+    }
+
+    /// 根据连接ID找到连接
+    pub fn connection(&self, conn_id: ConnectionId) -> anyhow::Result<Connection> {
+        self.connected_state
+            .lock()
+            .unwrap()
+            .connections.iter()
+            .find(|c| c.conn_id == conn_id)
+            .cloned()
+            .ok_or(anyhow!("connection {} not found", conn_id))
     }
 
     fn on_peer_connected(
@@ -230,8 +399,49 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
             peer: addr,
             conn_id,
             mtu: None,
+            subscriptions: HashMap::new(),
+            user_state: UserState::default(),
         });
+        let connections = connected_state.connections.len();
+        drop(connected_state);
         self.gap.set_conn_params_conf(addr, 10, 20, 0, 400)?;
+        // 建立连接后广播会停止，允许多个连接时继续广播
+        if self.advertising_policy.should_advertise(connections) {
+            self.gap.start_advertising()?;
+        }
+        Ok(())
+    }
+
+    /// 连接断开后移除连接，对它订阅的特征调用`on_unsubscribe`，再按策略重新广播
+    fn on_peer_disconnected(&self, conn_id: ConnectionId, addr: BdAddr) -> anyhow::Result<()> {
+        let (removed, connections) = {
+            let mut connected_state = self.connected_state.lock().unwrap();
+            let removed = connected_state.connections
+                .iter()
+                .position(|c| c.conn_id == conn_id)
+                .map(|i| connected_state.connections.remove(i));
+            // 发给这个连接的通知不用再发了，正在等待的确认也不会再有结果
+            connected_state.notify_queue.retain(|n| n.conn_id != conn_id);
+            if connected_state.notify_confirmed == Some(addr) {
+                connected_state.notify_confirmed = None;
+                self.send_next_notification(&mut connected_state)?;
+            }
+            (removed, connected_state.connections.len())
+        };
+
+        if let Some(connection) = removed {
+            for uuid in connection.subscriptions.keys() {
+                if let Some(characteristic) = self.notify_characteristics.get(uuid) {
+                    if let Err(e) = characteristic.on_unsubscribe(self.state.clone(), &connection) {
+                        log::error!("Failed to unsubscribe {:?}: {}", uuid, e);
+                    }
+                }
+            }
+        }
+
+        if self.advertising_policy.should_advertise(connections) {
+            self.gap.start_advertising()?;
+        }
         Ok(())
     }
 
@@ -267,6 +477,10 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
                 self.check_gatt_status(status)?;
                 self.on_characteristic_added(attr_handle, service_handle, char_uuid)?;
             }
+            GattsEvent::DescriptorAdded { status, attr_handle, service_handle, descr_uuid } => {
+                self.check_gatt_status(status)?;
+                self.on_descriptor_added(attr_handle, service_handle, descr_uuid)?;
+            }
             GattsEvent::Write {
                 conn_id,
                 trans_id,
@@ -288,18 +502,19 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
                     is_prep,
                     value
                 )?;
-                self.on_write(handle, value)?;
+                self.on_write(conn_id, handle, value)?;
             }
             GattsEvent::Read { conn_id, trans_id, addr, handle, offset, need_rsp, .. } => {
                 log::info!("{addr:?} read  conn_id:{:?}", conn_id);
                 // 返回响应
                 if need_rsp {
+                    let cccd = self.cccd_value(conn_id, handle);
+                    let value = match &cccd {
+                        Some(cccd) => &cccd[..],
+                        None => self.on_read(conn_id, handle)?,
+                    };
                     let mut response = GattResponse::new();
-                    response
-                        .attr_handle(handle)
-                        .auth_req(0)
-                        .offset(offset)
-                        .value(self.on_read(handle)?)?;
+                    response.attr_handle(handle).auth_req(0).offset(offset).value(value)?;
 
                     self.gatts.send_response(
                         gatt_if,
@@ -313,9 +528,14 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
             GattsEvent::PeerConnected { conn_id, addr, conn_params, .. } => {
                 self.on_peer_connected(conn_id, addr, conn_params)?;
             }
+            GattsEvent::PeerDisconnected { conn_id, addr, reason } => {
+                log::info!("{addr:?} disconnected conn_id:{:?} reason:{:?}", conn_id, reason);
+                self.on_peer_disconnected(conn_id, addr)?;
+            }
             GattsEvent::Confirm { status, .. } => {
-                self.check_gatt_status(status)?;
+                // 发送失败时也要继续发送队列中的下一条
                 self.confirm_notify()?;
+                self.check_gatt_status(status)?;
             }
             _ => {}
         }
@@ -370,47 +590,93 @@ impl<'a, T: Sync + Send + Clone> BLEApp<'a, T> {
         }
     }
 
+    /// 向订阅了该特征的连接发送通知或指示，`f`只会收到已经订阅的连接，没有订阅者时不会调用
+    ///
+    /// 数据会先放进队列，上一条确认后在Bluedroid的回调中发送下一条，所以这里不会阻塞，
+    /// 在特征的回调中调用也是安全的。
     pub fn notify<F>(&self, char_uuid: &BtUuid, f: F) -> anyhow::Result<()>
         where F: Fn(&[Connection], T) -> anyhow::Result<Vec<(&Connection, &[u8])>>
     {
-        let mut connected_state = self.connected_state.lock().unwrap();
-        let gatts_if = connected_state.gatt_if.ok_or(anyhow!("gatt_if not found"))?;
-        let attr_handle = connected_state.attr_handle_map
-            .iter()
-            .find_map(|(attr_handle, uuid)| (
-                if uuid == char_uuid {
-                    Some(*attr_handle)
-                } else {
-                    None
-                }
-            ))
-            .ok_or(anyhow!("attr_handle not found"))?;
-        let connections = connected_state.connections.clone();
+        let uuid: HashBtUuid = char_uuid.clone().into();
+        if !self.notify_characteristics.contains_key(&uuid) {
+            bail!("{:?} is not a notify characteristic", uuid);
+        }
+        // 先取出订阅者的快照，调用`f`时不持有锁，`f`里可以再访问连接状态
+        let (attr_handle, connections) = {
+            let connected_state = self.connected_state.lock().unwrap();
+            let attr_handle = connected_state.attr_handle_map
+                .iter()
+                .find_map(|(attr_handle, i)| (
+                    if *i == uuid {
+                        Some(*attr_handle)
+                    } else {
+                        None
+                    }
+                ))
+                .ok_or(anyhow!("attr_handle not found"))?;
+            let connections = connected_state.connections
+                .iter()
+                .filter(|c| c.subscriptions.contains_key(&uuid))
+                .cloned()
+                .collect::<Vec<_>>();
+            (attr_handle, connections)
+        };
+        if connections.is_empty() {
+            return Ok(());
+        }
         let connect_data = f(&connections, self.state.clone())?;
 
+        let mut connected_state = self.connected_state.lock().unwrap();
         for (conn, data) in connect_data {
             log::warn!("notify conn:{:?} data:{:?}", conn, data);
-            if connected_state.notify_confirmed.is_none() {
-                self.gatts.notify(gatts_if, conn.conn_id, attr_handle, data)?;
-                connected_state.notify_confirmed = Some(conn.peer);
-            } else {
-                connected_state = self.condvar.wait(connected_state).unwrap();
-            }
+            let Some(subscription) = conn.subscriptions.get(&uuid).copied() else {
+                continue;
+            };
+            connected_state.notify_queue.push_back(PendingNotification {
+                conn_id: conn.conn_id,
+                peer: conn.peer,
+                attr_handle,
+                subscription,
+                data: data.to_vec(),
+            });
         }
+        self.send_next_notification(&mut connected_state)
+    }
 
+    /// 没有等待确认的通知时，发送队列中的下一条
+    fn send_next_notification(&self, state: &mut ConnectedState) -> anyhow::Result<()> {
+        if state.notify_confirmed.is_some() {
+            return Ok(());
+        }
+        let gatts_if = state.gatt_if.ok_or(anyhow!("gatt_if not found"))?;
+        while let Some(next) = state.notify_queue.pop_front() {
+            // 排队期间连接可能已经断开
+            if !state.connections.iter().any(|c| c.conn_id == next.conn_id) {
+                continue;
+            }
+            match next.subscription {
+                Subscription::Indicate => {
+                    self.gatts.indicate(gatts_if, next.conn_id, next.attr_handle, &next.data)?;
+                }
+                Subscription::Notify => {
+                    self.gatts.notify(gatts_if, next.conn_id, next.attr_handle, &next.data)?;
+                }
+            }
+            state.notify_confirmed = Some(next.peer);
+            break;
+        }
         Ok(())
     }
 
     fn confirm_notify(&self) -> anyhow::Result<()> {
         let mut state = self.connected_state.lock().unwrap();
         if state.notify_confirmed.is_none() {
-            // 不应该发生：意味着我们收到了一个我们未发送的指示的确认。
-            unreachable!();
+            // 对端在确认之前断开时已经清除了等待状态
+            log::warn!("Received a confirm without a pending notify");
+            return Ok(());
         }
 
-        state.notify_confirmed = None; // 以便主循环可以发送下一个指示
-        self.condvar.notify_all();
-
-        Ok(())
+        state.notify_confirmed = None;
+        self.send_next_notification(&mut state)
     }
 }
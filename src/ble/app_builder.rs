@@ -2,13 +2,14 @@ use std::sync::Arc;
 
 use esp_idf_svc::bt::ble::{ gap::{ AdvConfiguration, EspBleGap }, gatt::server::EspGatts };
 
-use super::{ BLEApp, ExBtDriver, ExEspBleGap, ExEspGatts };
+use super::{ AdvertisingPolicy, BLEApp, ExBtDriver, ExEspBleGap, ExEspGatts };
 
 #[derive(Clone, Default)]
 pub struct BLEAppBuilder<'a, State: Sync + Send + Clone = ()> {
     pub app_id: Option<u16>,
     pub device_name: Option<&'a str>,
     pub adv_configuration: Option<AdvConfiguration<'a>>,
+    pub advertising_policy: Option<AdvertisingPolicy>,
     pub gap: Option<ExEspBleGap<'a>>,
     pub gatts: Option<ExEspGatts<'a>>,
     pub state: Option<State>,
@@ -20,6 +21,7 @@ impl<'a, State: Sync + Send + Clone> BLEAppBuilder<'a, State> {
             app_id: None,
             device_name: None,
             adv_configuration: None,
+            advertising_policy: None,
             gap: None,
             gatts: None,
             state: None,
@@ -41,6 +43,12 @@ impl<'a, State: Sync + Send + Clone> BLEAppBuilder<'a, State> {
         self
     }
 
+    /// 连接断开后是否重新广播，默认没有连接时重新广播
+    pub fn advertising_policy(&mut self, advertising_policy: AdvertisingPolicy) -> &mut Self {
+        self.advertising_policy = Some(advertising_policy);
+        self
+    }
+
     pub fn state(&mut self, state: State) -> &mut Self {
         self.state = Some(state);
         self
@@ -60,6 +68,7 @@ impl<'a, State: Sync + Send + Clone> BLEAppBuilder<'a, State> {
             self.gap.clone().unwrap(),
             self.gatts.clone().unwrap(),
             self.adv_configuration.clone().unwrap(),
+            self.advertising_policy.unwrap_or_default(),
             self.device_name
         )
     }
@@ -1,20 +1,37 @@
+//! 基于Bluedroid的BLE GATT服务框架
+//!
+//! 除了`state`中的类型，其余部分依赖Bluedroid，只在sdkconfig开启
+//! `CONFIG_BT_BLUEDROID_ENABLED`时编译。项目默认使用NimBLE，构建方法见`ble_test`示例（src/ble/test.rs）。
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 use esp_idf_svc::bt::{
     ble::{ gap::EspBleGap, gatt::{ server::EspGatts, GattCharacteristic, GattDescriptor } },
     Ble,
     BtDriver,
 };
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 use std::{ fmt::Debug, sync::Arc };
+mod state;
+pub use state::*;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 mod service;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 mod app;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub use service::Service;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub use app::*;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 mod app_builder;
 
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 type ExBtDriver<'a> = BtDriver<'a, Ble>;
 
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 type ExEspBleGap<'a> = Arc<EspBleGap<'a, Ble, Arc<ExBtDriver<'a>>>>;
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 type ExEspGatts<'a> = Arc<EspGatts<'a, Ble, Arc<ExBtDriver<'a>>>>;
 
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub trait CharacteristicExt: Debug + Sync + Send {
     fn characteristic(&self) -> GattCharacteristic;
     fn descriptors(&self) -> Vec<GattDescriptor> {
@@ -22,22 +39,29 @@ pub trait CharacteristicExt: Debug + Sync + Send {
     }
 }
 
+/// `conn`是发起请求的连接，可以用`conn.user_state`保存与对端相关的数据
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub trait ReadExt: CharacteristicExt {
     type State: Sync + Send + Clone;
-    fn on_read(&self, state: Self::State) -> anyhow::Result<&[u8]>;
+    fn on_read(&self, state: Self::State, conn: &Connection) -> anyhow::Result<&[u8]>;
 }
 
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub trait WriteExt: CharacteristicExt {
     type State: Sync + Send + Clone;
-    fn on_write(&self, state: Self::State, data: &[u8]) -> anyhow::Result<()>;
+    fn on_write(&self, state: Self::State, conn: &Connection, data: &[u8]) -> anyhow::Result<()>;
 }
 
+/// 支持通知或指示的特征，客户端写入CCCD订阅或取消订阅时调用对应的回调，
+/// 订阅了的连接断开时也会调用`on_unsubscribe`
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub trait NotifyExt: CharacteristicExt {
     type State: Sync + Send + Clone;
-    fn on_subscribe(&self, state: Self::State) -> anyhow::Result<()>;
-    fn on_unsubscribe(&self, state: Self::State) -> anyhow::Result<()>;
+    fn on_subscribe(&self, state: Self::State, conn: &Connection) -> anyhow::Result<()>;
+    fn on_unsubscribe(&self, state: Self::State, conn: &Connection) -> anyhow::Result<()>;
 }
 
+#[cfg(esp_idf_bt_bluedroid_enabled)]
 pub fn start<State: Send + Sync + Clone + 'static>(
     ble_app: BLEApp<'static, State>
 ) -> anyhow::Result<()> {
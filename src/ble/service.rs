@@ -1,13 +1,15 @@
 use esp_idf_svc::bt::ble::gatt::GattServiceId;
 use std::{ marker::PhantomData, sync::Arc };
-use super::{ ReadExt, WriteExt };
+use super::{ NotifyExt, ReadExt, WriteExt };
 
 #[derive(Debug, Clone)]
 pub struct Service<State: Sync + Send + Clone = ()> {
     pub service_id: GattServiceId,
+    /// 服务占用的句柄数，每个特征占2个，每个描述符占1个，通知特征自动添加的CCCD也要算上
     pub num_handles: u16,
     pub read_characteristics: Vec<Arc<dyn ReadExt<State = State>>>,
     pub write_characteristics: Vec<Arc<dyn WriteExt<State = State>>>,
+    pub notify_characteristics: Vec<Arc<dyn NotifyExt<State = State>>>,
     _p: std::marker::PhantomData<State>,
 }
 
@@ -21,6 +23,7 @@ impl<T: Sync + Send + Clone> Service<T> {
             num_handles,
             read_characteristics: Vec::new(),
             write_characteristics: Vec::new(),
+            notify_characteristics: Vec::new(),
             _p: PhantomData,
         }
     }
@@ -32,4 +35,9 @@ impl<T: Sync + Send + Clone> Service<T> {
     pub fn add_write_characteristic(&mut self, characteristic: Arc<dyn WriteExt<State = T>>) {
         self.write_characteristics.push(characteristic);
     }
+
+    /// 添加支持通知或指示的特征，会自动添加0x2902描述符（CCCD）
+    pub fn add_notify_characteristic(&mut self, characteristic: Arc<dyn NotifyExt<State = T>>) {
+        self.notify_characteristics.push(characteristic);
+    }
 }
@@ -0,0 +1,151 @@
+//! 连接相关的类型，不依赖Bluedroid，主机上也可以测试
+use std::{ any::Any, sync::{ Arc, Mutex } };
+
+/// 客户端特征配置描述符（CCCD）的UUID
+pub const CCCD_UUID: u16 = 0x2902;
+
+/// 每个连接的用户状态，特征回调可以在这里保存与对端相关的数据，例如是否已经认证
+///
+/// 克隆的`Connection`共享同一个状态。
+#[derive(Debug, Clone, Default)]
+pub struct UserState(Arc<Mutex<Option<Box<dyn Any + Send>>>>);
+
+impl UserState {
+    pub fn set<V: Any + Send>(&self, value: V) {
+        *self.0.lock().unwrap() = Some(Box::new(value));
+    }
+
+    /// 读取或修改状态，没有设置过或者类型不一致时为`None`
+    pub fn with<V: Any + Send, R>(&self, f: impl FnOnce(Option<&mut V>) -> R) -> R {
+        let mut value = self.0.lock().unwrap();
+        f(value.as_mut().and_then(|v| v.downcast_mut::<V>()))
+    }
+
+    /// 取出状态，类型不一致时保留原来的值并返回`None`
+    pub fn take<V: Any + Send>(&self) -> Option<V> {
+        let mut value = self.0.lock().unwrap();
+        match value.take()?.downcast::<V>() {
+            Ok(v) => Some(*v),
+            Err(other) => {
+                *value = Some(other);
+                None
+            }
+        }
+    }
+}
+
+/// 连接断开后是否重新广播，Bluedroid在建立连接时会自动停止广播
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdvertisingPolicy {
+    /// 不再广播，需要自己调用`start_advertising`
+    Never,
+    /// 没有任何连接时重新广播
+    #[default]
+    WhenIdle,
+    /// 连接数少于给定值时一直广播，允许多个中心设备同时连接
+    UpTo(usize),
+}
+
+impl AdvertisingPolicy {
+    /// 当前有`connections`个连接时是否应该广播
+    pub fn should_advertise(&self, connections: usize) -> bool {
+        match *self {
+            Self::Never => false,
+            Self::WhenIdle => connections == 0,
+            Self::UpTo(max) => connections < max,
+        }
+    }
+}
+
+/// 订阅方式，由客户端写入CCCD决定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    Notify,
+    Indicate,
+}
+
+impl Subscription {
+    /// 解析写入CCCD的值：bit0为通知，bit1为指示，都为0表示取消订阅
+    pub fn from_cccd(value: &[u8]) -> Option<Self> {
+        let bits = value.first().copied().unwrap_or(0);
+        if bits & 0b10 != 0 {
+            Some(Self::Indicate)
+        } else if bits & 0b01 != 0 {
+            Some(Self::Notify)
+        } else {
+            None
+        }
+    }
+
+    /// 读取CCCD时返回的值
+    pub fn to_cccd(subscription: Option<Self>) -> [u8; 2] {
+        match subscription {
+            Some(Self::Notify) => [1, 0],
+            Some(Self::Indicate) => [2, 0],
+            None => [0, 0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cccd() {
+        let cases: [(&[u8], Option<Subscription>); 6] = [
+            (&[], None),
+            (&[0, 0], None),
+            (&[1, 0], Some(Subscription::Notify)),
+            (&[2, 0], Some(Subscription::Indicate)),
+            // 同时开启时优先使用需要确认的指示
+            (&[3, 0], Some(Subscription::Indicate)),
+            // 高位保留，不影响结果
+            (&[0xfc, 0xff], None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(Subscription::from_cccd(value), expected, "{:?}", value);
+        }
+        for subscription in [None, Some(Subscription::Notify), Some(Subscription::Indicate)] {
+            let cccd = Subscription::to_cccd(subscription);
+            assert_eq!(Subscription::from_cccd(&cccd), subscription);
+        }
+        assert_eq!(Subscription::to_cccd(None), [0, 0]);
+    }
+
+    #[test]
+    fn advertising_policy() {
+        assert!(!AdvertisingPolicy::Never.should_advertise(0));
+        assert!(AdvertisingPolicy::WhenIdle.should_advertise(0));
+        assert!(!AdvertisingPolicy::WhenIdle.should_advertise(1));
+        let up_to = AdvertisingPolicy::UpTo(3);
+        assert!(up_to.should_advertise(2));
+        assert!(!up_to.should_advertise(3));
+        assert!(!AdvertisingPolicy::UpTo(0).should_advertise(0));
+        assert_eq!(AdvertisingPolicy::default(), AdvertisingPolicy::WhenIdle);
+    }
+
+    #[test]
+    fn user_state() {
+        let state = UserState::default();
+        assert_eq!(state.with(|v: Option<&mut u32>| v.copied()), None);
+        assert_eq!(state.take::<u32>(), None);
+
+        state.set(1u32);
+        // 克隆的状态是共享的
+        let shared = state.clone();
+        shared.with(|v: Option<&mut u32>| {
+            *v.unwrap() += 1;
+        });
+        assert_eq!(state.with(|v: Option<&mut u32>| v.copied()), Some(2));
+
+        // 类型不一致时读不到，也不会把原来的值取走
+        assert_eq!(state.with(|v: Option<&mut String>| v.cloned()), None);
+        assert_eq!(state.take::<String>(), None);
+        assert_eq!(state.take::<u32>(), Some(2));
+        assert_eq!(shared.take::<u32>(), None);
+
+        state.set(String::from("authenticated"));
+        assert_eq!(shared.take::<String>().as_deref(), Some("authenticated"));
+    }
+}
@@ -1,135 +1,157 @@
-// use std::sync::Arc;
+//! Bluedroid版BLE框架的示例：一个可读写的特征和一个可订阅的特征，每5秒向订阅者发送一次通知
+//!
+//! 项目默认使用NimBLE，这个示例需要换成Bluedroid的sdkconfig构建：
+//!
+//! ```sh
+//! ESP_IDF_SDKCONFIG_DEFAULTS="sdkconfig.defaults;sdkconfig.bluedroid.defaults" \
+//!     cargo build --bin ble_test --no-default-features --features bluedroid
+//! ```
+use std::sync::Arc;
 
-// use enumset::enum_set;
-// use esp_idf_svc::{
-//     bt::{
-//         ble::{
-//             gap::AdvConfiguration,
-//             gatt::{
-//                 AutoResponse,
-//                 GattCharacteristic,
-//                 GattDescriptor,
-//                 GattId,
-//                 GattServiceId,
-//                 Permission,
-//                 Property,
-//             },
-//         },
-//         Ble,
-//         BtDriver,
-//         BtUuid,
-//     },
-//     hal::delay::FreeRtos,
-// };
-// use rust_embedded_study::{ ble::{ self, CharacteristicExt, ReadExt, Service, WriteExt }, init };
+use enumset::enum_set;
+use esp_idf_svc::{
+    bt::{
+        ble::{
+            gap::AdvConfiguration,
+            gatt::{
+                AutoResponse,
+                GattCharacteristic,
+                GattDescriptor,
+                GattId,
+                GattServiceId,
+                Permission,
+                Property,
+            },
+        },
+        Ble,
+        BtDriver,
+        BtUuid,
+    },
+    hal::delay::FreeRtos,
+};
+use rust_embedded_study::{ ble::{ self, AdvertisingPolicy, CharacteristicExt, Connection, NotifyExt, ReadExt, Service, WriteExt }, init };
 
-// #[derive(Debug, Clone, Default)]
-// struct TestReadWrite;
+#[derive(Debug, Clone, Default)]
+struct TestReadWrite;
 
-// impl CharacteristicExt for TestReadWrite {
-//     fn characteristic(&self) -> esp_idf_svc::bt::ble::gatt::GattCharacteristic {
-//         GattCharacteristic::new(
-//             BtUuid::uuid16(0x1a19),
-//             enum_set!(Permission::Read | Permission::Write),
-//             enum_set!(Property::Notify),
-//             200,
-//             AutoResponse::ByApp
-//         )
-//     }
-//     fn descriptors(&self) -> Vec<esp_idf_svc::bt::ble::gatt::GattDescriptor> {
-//         vec![GattDescriptor {
-//             uuid: BtUuid::uuid16(0x1143),
-//             permissions: enum_set!(Permission::Read | Permission::Write),
-//         }]
-//     }
-// }
+impl CharacteristicExt for TestReadWrite {
+    fn characteristic(&self) -> esp_idf_svc::bt::ble::gatt::GattCharacteristic {
+        GattCharacteristic::new(
+            BtUuid::uuid16(0x1a19),
+            enum_set!(Permission::Read | Permission::Write),
+            enum_set!(Property::Read | Property::Notify),
+            200,
+            AutoResponse::ByApp
+        )
+    }
+    fn descriptors(&self) -> Vec<esp_idf_svc::bt::ble::gatt::GattDescriptor> {
+        vec![GattDescriptor {
+            uuid: BtUuid::uuid16(0x1143),
+            permissions: enum_set!(Permission::Read | Permission::Write),
+        }]
+    }
+}
 
-// impl ReadExt for TestReadWrite {
-//     type State = ();
-//     fn on_read(&self, _state: Self::State) -> anyhow::Result<&[u8]> {
-//         Ok(&[2])
-//     }
-// }
+impl ReadExt for TestReadWrite {
+    type State = ();
+    fn on_read(&self, _state: Self::State, _conn: &Connection) -> anyhow::Result<&[u8]> {
+        Ok(&[2])
+    }
+}
 
-// #[derive(Debug, Clone, Default)]
-// struct TestReadWrite2;
+impl NotifyExt for TestReadWrite {
+    type State = ();
+    fn on_subscribe(&self, _state: Self::State, conn: &Connection) -> anyhow::Result<()> {
+        log::info!("{:?} subscribed", conn.peer);
+        Ok(())
+    }
+    fn on_unsubscribe(&self, _state: Self::State, conn: &Connection) -> anyhow::Result<()> {
+        log::info!("{:?} unsubscribed", conn.peer);
+        Ok(())
+    }
+}
 
-// impl CharacteristicExt for TestReadWrite2 {
-//     fn characteristic(&self) -> esp_idf_svc::bt::ble::gatt::GattCharacteristic {
-//         GattCharacteristic::new(
-//             BtUuid::uuid16(0xa223),
-//             enum_set!(Permission::Write | Permission::Read),
-//             enum_set!(Property::Write | Property::Read),
-//             200,
-//             AutoResponse::ByApp
-//         )
-//     }
+#[derive(Debug, Clone, Default)]
+struct TestReadWrite2;
 
-//     fn descriptors(&self) -> Vec<esp_idf_svc::bt::ble::gatt::GattDescriptor> {
-//         vec![GattDescriptor {
-//             uuid: BtUuid::uuid16(0x2902),
-//             permissions: enum_set!(Permission::Read | Permission::Write),
-//         }]
-//     }
-// }
+impl CharacteristicExt for TestReadWrite2 {
+    fn characteristic(&self) -> esp_idf_svc::bt::ble::gatt::GattCharacteristic {
+        GattCharacteristic::new(
+            BtUuid::uuid16(0xa223),
+            enum_set!(Permission::Write | Permission::Read),
+            enum_set!(Property::Write | Property::Read),
+            200,
+            AutoResponse::ByApp
+        )
+    }
 
-// impl WriteExt for TestReadWrite2 {
-//     type State = ();
-//     fn on_write(&self, _state: Self::State, data: &[u8]) -> anyhow::Result<()> {
-//         log::warn!("write: {:?}", data);
-//         Ok(())
-//     }
-// }
+}
 
-// impl ReadExt for TestReadWrite2 {
-//     type State = ();
-//     fn on_read(&self, _state: Self::State) -> anyhow::Result<&[u8]> {
-//         Ok(&[1u8, 2u8, 3u8])
-//     }
-// }
+impl WriteExt for TestReadWrite2 {
+    type State = ();
+    fn on_write(&self, _state: Self::State, conn: &Connection, data: &[u8]) -> anyhow::Result<()> {
+        // 记录每个连接写入的次数
+        let count = conn.user_state.with(|count: Option<&mut u32>| count.map(|c| { *c += 1; *c }));
+        if count.is_none() {
+            conn.user_state.set(1u32);
+        }
+        log::warn!("{:?} write: {:?}", conn.peer, data);
+        Ok(())
+    }
+}
 
-// fn main() -> anyhow::Result<()> {
-//     let (_, peripherals, nvs) = init()?;
-//     let driver = BtDriver::<Ble>::new(peripherals.modem, Some(nvs))?;
-//     let mut ble_app = ble::BLEApp
-//         ::builder()
-//         .device_name("esp32c3")
-//         .app_id(0)
-//         .driver(driver)?
-//         .state(())
-//         .adv_configuration(AdvConfiguration {
-//             include_name: true,
-//             include_txpower: true,
-//             flag: 2,
-//             ..Default::default()
-//         })
-//         .build();
-//     let mut service = Service::new(
-//         GattServiceId {
-//             id: GattId {
-//                 uuid: BtUuid::uuid16(0xff32),
-//                 inst_id: 0,
-//             },
-//             is_primary: true,
-//         },
-//         8
-//     );
+impl ReadExt for TestReadWrite2 {
+    type State = ();
+    fn on_read(&self, _state: Self::State, _conn: &Connection) -> anyhow::Result<&[u8]> {
+        Ok(&[1u8, 2u8, 3u8])
+    }
+}
 
-//     service.add_write_characteristic(Arc::new(TestReadWrite2));
-//     service.add_read_characteristic(Arc::new(TestReadWrite));
-//     service.add_read_characteristic(Arc::new(TestReadWrite2));
-//     ble_app.add_service(service);
-//     log::info!("start ble {:#?}", ble_app.services);
-//     ble::start(ble_app.clone())?;
+fn main() -> anyhow::Result<()> {
+    let (_, peripherals, nvs) = init()?;
+    let driver = BtDriver::<Ble>::new(peripherals.modem, Some(nvs))?;
+    let mut ble_app = ble::BLEApp
+        ::builder()
+        .device_name("esp32c3")
+        .app_id(0)
+        .driver(driver)?
+        .state(())
+        .advertising_policy(AdvertisingPolicy::UpTo(2))
+        .adv_configuration(AdvConfiguration {
+            include_name: true,
+            include_txpower: true,
+            flag: 2,
+            ..Default::default()
+        })
+        .build();
+    let mut service = Service::new(
+        GattServiceId {
+            id: GattId {
+                uuid: BtUuid::uuid16(0xff32),
+                inst_id: 0,
+            },
+            is_primary: true,
+        },
+        12
+    );
 
-//     loop {
-//         ble_app.notify(&BtUuid::uuid16(0x1a19), |conns, _state| {
-//             let res = conns
-//                 .iter()
-//                 .map(|i| { (i, &[1u8] as &[u8]) })
-//                 .collect::<Vec<_>>();
-//             Ok(res)
-//         })?;
-//         FreeRtos::delay_ms(5000);
-//     }
-// }
+    service.add_write_characteristic(Arc::new(TestReadWrite2));
+    service.add_read_characteristic(Arc::new(TestReadWrite));
+    // 自动添加CCCD，只有订阅了的连接才会收到通知
+    service.add_notify_characteristic(Arc::new(TestReadWrite));
+    service.add_read_characteristic(Arc::new(TestReadWrite2));
+    ble_app.add_service(service);
+    log::info!("start ble {:#?}", ble_app.services);
+    ble::start(ble_app.clone())?;
+
+    loop {
+        ble_app.notify(&BtUuid::uuid16(0x1a19), |conns, _state| {
+            let res = conns
+                .iter()
+                .map(|i| { (i, &[1u8] as &[u8]) })
+                .collect::<Vec<_>>();
+            Ok(res)
+        })?;
+        FreeRtos::delay_ms(5000);
+    }
+}
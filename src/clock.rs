@@ -0,0 +1,356 @@
+//! 墙上时间
+//!
+//! Wi-Fi连接后通过SNTP同步时间，`Clock`报告时间是否已经同步，并按POSIX TZ字符串换算本地时间。
+//! 没有同步之前系统时间从1970年开始，定时任务和依赖日期的功能都应该先检查`is_synced`。
+use std::time::{ Duration, SystemTime, UNIX_EPOCH };
+
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+use crate::wifi::{ Subscriber, Watch };
+
+/// 最多可以配置的SNTP服务器数，与`CONFIG_LWIP_SNTP_MAX_SERVERS`一致
+pub const MAX_SERVERS: usize = 3;
+
+/// 时间同步的配置
+///
+/// ```json
+/// { "servers": ["ntp.aliyun.com", "pool.ntp.org"], "timezone": "CST-8" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockSettings {
+    pub servers: Vec<String>,
+    /// POSIX TZ字符串，例如`CST-8`、`CET-1CEST,M3.5.0,M10.5.0/3`
+    pub timezone: String,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self {
+            servers: vec!["ntp.aliyun.com".into(), "pool.ntp.org".into()],
+            timezone: "CST-8".into(),
+        }
+    }
+}
+
+impl ClockSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.servers.is_empty() || self.servers.len() > MAX_SERVERS {
+            bail!("expected 1 to {} SNTP servers", MAX_SERVERS);
+        }
+        if self.servers.iter().any(|s| s.trim().is_empty()) {
+            bail!("empty SNTP server");
+        }
+        if self.timezone.trim().is_empty() {
+            bail!("empty timezone");
+        }
+        Ok(())
+    }
+}
+
+/// 本地时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1到12
+    pub month: u8,
+    /// 1到31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// 0表示星期日
+    pub weekday: u8,
+    /// 一年中的第几天，1月1日为1
+    pub ordinal: u16,
+    /// 与UTC的偏移（秒），东八区为28800
+    pub utc_offset: i32,
+}
+
+impl LocalTime {
+    /// 把Unix时间按给定的UTC偏移换算为本地时间
+    pub fn from_unix(secs: i64, utc_offset: i32) -> Self {
+        let local = secs + (utc_offset as i64);
+        let days = local.div_euclid(86400);
+        let secs_of_day = local.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: ((secs_of_day / 60) % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            // 1970年1月1日是星期四
+            weekday: (days + 4).rem_euclid(7) as u8,
+            ordinal: (days - days_from_civil(year, 1, 1) + 1) as u16,
+            utc_offset,
+        }
+    }
+
+    /// 从零点开始的分钟数
+    pub fn minute_of_day(&self) -> u16 {
+        (self.hour as u16) * 60 + (self.minute as u16)
+    }
+
+    /// 本地日期从1970年1月1日开始的天数
+    pub fn days(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day)
+    }
+}
+
+/// 从1970年1月1日开始的天数
+pub fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let y = (year as i64) - ((month <= 2) as i64);
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + (day as i64) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// `days_from_civil`的逆运算
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = (yoe + era * 400 + ((month <= 2) as i64)) as i32;
+    (year, month, day)
+}
+
+/// 系统时钟的同步状态，可以在多个线程之间共享
+#[derive(Clone)]
+pub struct Clock {
+    synced: Watch<bool>,
+    #[cfg(target_os = "espidf")]
+    sntp: std::sync::Arc<std::sync::Mutex<Option<esp_idf_svc::sntp::EspSntp<'static>>>>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            synced: Watch::new(false),
+            #[cfg(target_os = "espidf")]
+            sntp: Default::default(),
+        }
+    }
+
+    /// 时间是否已经同步过
+    pub fn is_synced(&self) -> bool {
+        self.synced.get()
+    }
+
+    /// 订阅同步状态的变化
+    pub fn subscribe(&self) -> Subscriber<bool> {
+        self.synced.subscribe()
+    }
+
+    /// 阻塞直到时间同步，`timeout`为`None`表示一直等待，返回是否已经同步
+    pub fn wait_synced(&self, timeout: Option<Duration>) -> bool {
+        self.subscribe()
+            .wait_for(|synced| *synced, timeout)
+            .is_some()
+    }
+
+    /// 标记时间已经同步，例如从RTC或者其它来源设置了时间
+    pub fn mark_synced(&self) {
+        if !self.synced.get() {
+            self.synced.set(true);
+        }
+    }
+
+    /// 当前时间，没有同步时返回`None`
+    pub fn now(&self) -> Option<SystemTime> {
+        self.is_synced().then(SystemTime::now)
+    }
+
+    /// 当前的Unix时间（秒），没有同步时返回`None`
+    pub fn unix(&self) -> Option<i64> {
+        self.now()
+            .and_then(|now| now.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64)
+    }
+
+    /// 当前的本地时间，没有同步时返回`None`
+    pub fn local_now(&self) -> Option<LocalTime> {
+        self.unix().map(local_time)
+    }
+}
+
+/// 按当前时区换算本地时间
+#[cfg(target_os = "espidf")]
+pub fn local_time(secs: i64) -> LocalTime {
+    use esp_idf_svc::sys;
+
+    let time = secs as sys::time_t;
+    let mut tm: sys::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        sys::localtime_r(&time, &mut tm);
+    }
+    // 把本地时间当作UTC计算，与实际的UTC相减得到时区偏移
+    let as_utc =
+        days_from_civil(tm.tm_year + 1900, (tm.tm_mon + 1) as u8, tm.tm_mday as u8) * 86400 +
+        (tm.tm_hour as i64) * 3600 +
+        (tm.tm_min as i64) * 60 +
+        (tm.tm_sec as i64);
+    LocalTime::from_unix(secs, (as_utc - secs) as i32)
+}
+
+/// 主机上没有时区配置，使用UTC
+#[cfg(not(target_os = "espidf"))]
+pub fn local_time(secs: i64) -> LocalTime {
+    LocalTime::from_unix(secs, 0)
+}
+
+#[cfg(target_os = "espidf")]
+mod sntp {
+    use std::ffi::CString;
+
+    use anyhow::Result;
+    use esp_idf_svc::{ sntp::{ EspSntp, SntpConf }, sys };
+
+    use super::{ Clock, ClockSettings };
+
+    /// 设置本地时区，影响`local_time`和日志中的时间
+    pub fn set_timezone(tz: &str) -> Result<()> {
+        let tz = CString::new(tz)?;
+        unsafe {
+            sys::setenv(c"TZ".as_ptr(), tz.as_ptr(), 1);
+            sys::tzset();
+        }
+        Ok(())
+    }
+
+    impl Clock {
+        /// 设置时区并启动SNTP，已经启动时按新的配置重新启动
+        ///
+        /// 应该在Wi-Fi连接之后调用，之后SNTP会定期自动同步。
+        pub fn start_sntp(&self, settings: &ClockSettings) -> Result<()> {
+            settings.validate()?;
+            set_timezone(&settings.timezone)?;
+
+            let mut sntp = self.sntp.lock().unwrap();
+            // 同时只能有一个SNTP实例
+            sntp.take();
+            let mut conf = SntpConf::default();
+            // 没有配置的槽位要清空，否则会继续使用默认的服务器
+            let mut servers = settings.servers.iter().map(String::as_str);
+            for slot in conf.servers.iter_mut() {
+                *slot = servers.next().unwrap_or("");
+            }
+            let synced = self.synced.clone();
+            *sntp = Some(
+                EspSntp::new_with_callback(&conf, move |_| {
+                    if !synced.get() {
+                        log::info!("时间已同步");
+                        synced.set(true);
+                    }
+                })?
+            );
+            log::info!("启动SNTP: {:?}，时区: {}", settings.servers, settings.timezone);
+            Ok(())
+        }
+
+        /// SNTP是否已经启动
+        pub fn sntp_running(&self) -> bool {
+            self.sntp.lock().unwrap().is_some()
+        }
+    }
+}
+
+#[cfg(target_os = "espidf")]
+pub use sntp::set_timezone;
+
+#[cfg(target_os = "espidf")]
+pub use store::ClockStore;
+
+#[cfg(target_os = "espidf")]
+mod store {
+    use anyhow::Result;
+    use esp_idf_svc::nvs::{ EspDefaultNvsPartition, EspNvs, NvsDefault };
+
+    use super::ClockSettings;
+    use crate::storage::{ read_json, write_json };
+
+    const NAMESPACE: &str = "clock";
+    const SETTINGS_KEY: &str = "settings";
+
+    /// 保存在NVS中的时间同步配置
+    pub struct ClockStore {
+        nvs: EspNvs<NvsDefault>,
+    }
+
+    impl ClockStore {
+        pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+            Ok(Self { nvs: EspNvs::new(partition, NAMESPACE, true)? })
+        }
+
+        /// 没有保存过时使用默认配置
+        pub fn settings(&self) -> Result<ClockSettings> {
+            Ok(read_json(&self.nvs, SETTINGS_KEY)?.unwrap_or_default())
+        }
+
+        pub fn set_settings(&mut self, settings: &ClockSettings) -> Result<()> {
+            settings.validate()?;
+            write_json(&mut self.nvs, SETTINGS_KEY, settings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_unix_time_to_local_time() {
+        // 2024-02-29 23:30:00 UTC，星期四
+        let utc = LocalTime::from_unix(1709249400, 0);
+        assert_eq!((utc.year, utc.month, utc.day, utc.hour, utc.minute), (2024, 2, 29, 23, 30));
+        assert_eq!((utc.weekday, utc.ordinal), (4, 60));
+
+        // 东八区已经是3月1日星期五
+        let cst = LocalTime::from_unix(1709249400, 8 * 3600);
+        assert_eq!((cst.year, cst.month, cst.day, cst.hour), (2024, 3, 1, 7));
+        assert_eq!((cst.weekday, cst.ordinal, cst.minute_of_day()), (5, 61, 7 * 60 + 30));
+
+        let before_epoch = LocalTime::from_unix(-1, 0);
+        assert_eq!((before_epoch.year, before_epoch.month, before_epoch.day), (1969, 12, 31));
+        assert_eq!(before_epoch.second, 59);
+    }
+
+    #[test]
+    fn clock_reports_sync_state() {
+        let clock = Clock::new();
+        assert!(!clock.is_synced());
+        assert_eq!(clock.local_now(), None);
+        assert!(!clock.wait_synced(Some(Duration::from_millis(10))));
+
+        let setter = clock.clone();
+        let thread = std::thread::spawn(move || setter.mark_synced());
+        assert!(clock.wait_synced(Some(Duration::from_secs(1))));
+        thread.join().unwrap();
+        assert!(clock.local_now().is_some());
+    }
+
+    #[test]
+    fn validates_settings() {
+        assert!(ClockSettings::default().validate().is_ok());
+        let too_many = ClockSettings { servers: vec!["a".into(); 4], ..Default::default() };
+        assert!(too_many.validate().is_err());
+        let no_tz = ClockSettings { timezone: " ".into(), ..Default::default() };
+        assert!(no_tz.validate().is_err());
+    }
+}
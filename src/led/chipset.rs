@@ -0,0 +1,204 @@
+//! 灯珠芯片描述
+//!
+//! 不同型号的灯珠在时序、字节顺序和通道数上都不一样，
+//! 驱动通过`Chipset`来决定如何把帧缓冲编码成脉冲。
+use std::time::Duration;
+
+use super::{ encoder::{ self, Symbol, Timing }, RGBW8 };
+
+/// 灯珠接收数据的字节顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorOrder {
+    RGB,
+    RBG,
+    GRB,
+    GBR,
+    BRG,
+    BGR,
+    RGBW,
+    GRBW,
+}
+
+impl ColorOrder {
+    /// 每颗灯珠占用的字节数
+    pub fn bytes_per_pixel(&self) -> usize {
+        if self.has_white() { 4 } else { 3 }
+    }
+
+    /// 是否有独立的白色通道
+    pub fn has_white(&self) -> bool {
+        matches!(self, ColorOrder::RGBW | ColorOrder::GRBW)
+    }
+
+    /// 按当前顺序排列像素的各个通道，只有前`bytes_per_pixel`个字节有效
+    pub fn bytes(&self, p: RGBW8) -> [u8; 4] {
+        match self {
+            ColorOrder::RGB => [p.r, p.g, p.b, 0],
+            ColorOrder::RBG => [p.r, p.b, p.g, 0],
+            ColorOrder::GRB => [p.g, p.r, p.b, 0],
+            ColorOrder::GBR => [p.g, p.b, p.r, 0],
+            ColorOrder::BRG => [p.b, p.r, p.g, 0],
+            ColorOrder::BGR => [p.b, p.g, p.r, 0],
+            ColorOrder::RGBW => [p.r, p.g, p.b, p.w],
+            ColorOrder::GRBW => [p.g, p.r, p.b, p.w],
+        }
+    }
+
+    /// 按当前顺序把像素写入字节流
+    pub fn write(&self, p: RGBW8, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.bytes(p)[..self.bytes_per_pixel()]);
+    }
+}
+
+/// 灯珠芯片描述：时序、字节顺序以及白色通道的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chipset {
+    pub name: &'static str,
+    pub timing: Timing,
+    pub order: ColorOrder,
+    /// 对于RGBW灯珠，是否在像素没有白色分量时自动从RGB中提取
+    pub auto_white: bool,
+}
+
+impl Chipset {
+    pub const WS2812B: Chipset = Chipset {
+        name: "WS2812B",
+        timing: Timing::WS2812B,
+        order: ColorOrder::GRB,
+        auto_white: false,
+    };
+
+    pub const SK6812_RGBW: Chipset = Chipset {
+        name: "SK6812-RGBW",
+        timing: Timing {
+            t0h: Duration::from_nanos(300),
+            t0l: Duration::from_nanos(900),
+            t1h: Duration::from_nanos(600),
+            t1l: Duration::from_nanos(600),
+            reset: Duration::from_micros(80),
+        },
+        order: ColorOrder::GRBW,
+        auto_white: true,
+    };
+
+    /// 12V WS2811灯串（800kHz高速模式）
+    pub const WS2811: Chipset = Chipset {
+        name: "WS2811",
+        timing: Timing {
+            t0h: Duration::from_nanos(250),
+            t0l: Duration::from_nanos(1000),
+            t1h: Duration::from_nanos(600),
+            t1l: Duration::from_nanos(650),
+            reset: Duration::from_micros(280),
+        },
+        order: ColorOrder::RGB,
+        auto_white: false,
+    };
+
+    pub const WS2815: Chipset = Chipset {
+        name: "WS2815",
+        timing: Timing {
+            t0h: Duration::from_nanos(300),
+            t0l: Duration::from_nanos(900),
+            t1h: Duration::from_nanos(900),
+            t1l: Duration::from_nanos(300),
+            reset: Duration::from_micros(280),
+        },
+        order: ColorOrder::GRB,
+        auto_white: false,
+    };
+
+    /// 修改字节顺序，用于同一芯片但接线顺序不同的灯带
+    pub const fn with_order(mut self, order: ColorOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// 每颗灯珠占用的字节数
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.order.bytes_per_pixel()
+    }
+
+    /// 按芯片的通道数处理白色分量
+    pub fn convert(&self, p: RGBW8) -> RGBW8 {
+        if !self.order.has_white() {
+            // 三通道灯珠没有白色灯珠，把白色叠加到RGB上
+            RGBW8::from(p.to_rgb())
+        } else if self.auto_white {
+            p.with_auto_white()
+        } else {
+            p
+        }
+    }
+
+    /// 按芯片要求依次产生一帧像素的字节，不需要分配整帧的缓冲
+    pub fn bytes<'a>(&self, pixels: &'a [RGBW8]) -> impl Iterator<Item = u8> + 'a {
+        let chipset = *self;
+        let len = self.bytes_per_pixel();
+        pixels.iter().flat_map(move |p| chipset.order.bytes(chipset.convert(*p)).into_iter().take(len))
+    }
+
+    /// 按芯片要求把一帧像素转换成字节流
+    pub fn pixel_bytes(&self, pixels: &[RGBW8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(pixels.len() * self.bytes_per_pixel());
+        bytes.extend(self.bytes(pixels));
+        bytes
+    }
+
+    /// 把一帧像素编码成脉冲序列，用于在主机上测试，驱动用同样的码元换成预先算好的RMT脉冲
+    pub fn encode(&self, pixels: &[RGBW8]) -> Vec<Symbol> {
+        encoder::encode_bytes(&self.pixel_bytes(pixels), &self.timing)
+    }
+}
+
+impl Default for Chipset {
+    fn default() -> Self {
+        Self::WS2812B
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::RGB8;
+
+    #[test]
+    fn color_orders() {
+        let p = RGBW8::new(1, 2, 3, 4);
+        let cases: [(ColorOrder, &[u8]); 8] = [
+            (ColorOrder::RGB, &[1, 2, 3]),
+            (ColorOrder::RBG, &[1, 3, 2]),
+            (ColorOrder::GRB, &[2, 1, 3]),
+            (ColorOrder::GBR, &[2, 3, 1]),
+            (ColorOrder::BRG, &[3, 1, 2]),
+            (ColorOrder::BGR, &[3, 2, 1]),
+            (ColorOrder::RGBW, &[1, 2, 3, 4]),
+            (ColorOrder::GRBW, &[2, 1, 3, 4]),
+        ];
+        for (order, expected) in cases {
+            let mut out = Vec::new();
+            order.write(p, &mut out);
+            assert_eq!(out, expected, "{:?}", order);
+            assert_eq!(order.bytes_per_pixel(), expected.len());
+        }
+    }
+
+    #[test]
+    fn chipset_white_handling() {
+        let pixels = [RGBW8::new(200, 100, 50, 0), RGBW8::new(10, 20, 30, 5)];
+        // SK6812 RGBW：没有白色分量时从RGB中提取公共部分，已有白色分量时原样发送
+        let sk6812 = Chipset::SK6812_RGBW;
+        assert_eq!(sk6812.pixel_bytes(&pixels), [50, 150, 0, 50, 20, 10, 30, 5]);
+        let manual = Chipset { auto_white: false, ..sk6812 };
+        assert_eq!(manual.pixel_bytes(&pixels), [100, 200, 50, 0, 20, 10, 30, 5]);
+        let rgbw = sk6812.with_order(ColorOrder::RGBW);
+        assert_eq!(rgbw.pixel_bytes(&pixels[..1]), [150, 50, 0, 50]);
+
+        // 三通道灯珠把白色叠加到RGB上，超过255时饱和
+        let ws2812 = Chipset::WS2812B;
+        assert_eq!(ws2812.pixel_bytes(&pixels), [100, 200, 50, 25, 15, 35]);
+        assert_eq!(RGBW8::new(250, 0, 0, 10).to_rgb(), RGB8::new(255, 10, 10));
+        assert_eq!(ws2812.encode(&pixels).len(), 2 * 3 * 16 + 1);
+        assert_eq!(sk6812.encode(&pixels).len(), 2 * 4 * 16 + 1);
+    }
+}
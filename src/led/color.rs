@@ -0,0 +1,362 @@
+//! 颜色模型
+//!
+//! 提供HSV、HSL和色温（开尔文）到`RGB8`的转换，以及在Oklab感知色彩空间中的插值，
+//! 用于平滑的颜色渐变。
+use serde::{ Deserialize, Serialize };
+
+use super::RGB8;
+
+/// 色相/饱和度/明度，`h`为0-360度，`s`和`v`为0-1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hsv {
+    pub h: f32,
+    pub s: f32,
+    pub v: f32,
+}
+
+/// 色相/饱和度/亮度，`h`为0-360度，`s`和`l`为0-1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hsl {
+    pub h: f32,
+    pub s: f32,
+    pub l: f32,
+}
+
+/// 相关色温（开尔文），支持1000K到40000K
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cct {
+    pub kelvin: u16,
+}
+
+/// Oklab感知色彩空间，在这里做插值亮度变化更均匀
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+/// 前端可以用任意一种方式描述颜色
+///
+/// ```json
+/// { "r": 255, "g": 0, "b": 0 }
+/// { "h": 120, "s": 1, "v": 0.5 }
+/// { "h": 120, "s": 1, "l": 0.5 }
+/// { "kelvin": 2700 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ColorSpec {
+    Rgb(RGB8),
+    Hsv(Hsv),
+    Hsl(Hsl),
+    Cct(Cct),
+}
+
+impl ColorSpec {
+    pub fn to_rgb(&self) -> RGB8 {
+        match *self {
+            ColorSpec::Rgb(c) => c,
+            ColorSpec::Hsv(c) => c.into(),
+            ColorSpec::Hsl(c) => c.into(),
+            ColorSpec::Cct(c) => c.into(),
+        }
+    }
+}
+
+impl From<ColorSpec> for RGB8 {
+    fn from(value: ColorSpec) -> Self {
+        value.to_rgb()
+    }
+}
+
+impl Hsv {
+    pub fn new(h: f32, s: f32, v: f32) -> Self {
+        Self { h, s, v }
+    }
+
+    /// 沿色相环较短的方向插值
+    pub fn lerp(&self, to: &Hsv, t: f32) -> Hsv {
+        let t = t.clamp(0.0, 1.0);
+        let mut dh = normalize_hue(to.h) - normalize_hue(self.h);
+        if dh > 180.0 {
+            dh -= 360.0;
+        } else if dh < -180.0 {
+            dh += 360.0;
+        }
+        Hsv {
+            h: normalize_hue(self.h + dh * t),
+            s: self.s + (to.s - self.s) * t,
+            v: self.v + (to.v - self.v) * t,
+        }
+    }
+}
+
+impl Hsl {
+    pub fn new(h: f32, s: f32, l: f32) -> Self {
+        Self { h, s, l }
+    }
+}
+
+impl Cct {
+    pub fn new(kelvin: u16) -> Self {
+        Self { kelvin }
+    }
+}
+
+fn normalize_hue(h: f32) -> f32 {
+    h.rem_euclid(360.0)
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// 由色相、色度和最小分量得到RGB，HSV和HSL共用
+fn from_hue_chroma(h: f32, c: f32, m: f32) -> RGB8 {
+    let h = normalize_hue(h) / 60.0;
+    let x = c * (1.0 - ((h % 2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    RGB8::new(to_u8(r + m), to_u8(g + m), to_u8(b + m))
+}
+
+impl From<Hsv> for RGB8 {
+    fn from(hsv: Hsv) -> Self {
+        let s = hsv.s.clamp(0.0, 1.0);
+        let v = hsv.v.clamp(0.0, 1.0);
+        let c = v * s;
+        from_hue_chroma(hsv.h, c, v - c)
+    }
+}
+
+impl From<RGB8> for Hsv {
+    fn from(rgb: RGB8) -> Self {
+        let r = (rgb.r as f32) / 255.0;
+        let g = (rgb.g as f32) / 255.0;
+        let b = (rgb.b as f32) / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let h = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max == 0.0 { 0.0 } else { delta / max };
+        Hsv { h, s, v: max }
+    }
+}
+
+impl From<Hsl> for RGB8 {
+    fn from(hsl: Hsl) -> Self {
+        let s = hsl.s.clamp(0.0, 1.0);
+        let l = hsl.l.clamp(0.0, 1.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        from_hue_chroma(hsl.h, c, l - c / 2.0)
+    }
+}
+
+impl From<Cct> for RGB8 {
+    /// Tanner Helland的黑体辐射近似公式
+    fn from(cct: Cct) -> Self {
+        let t = (cct.kelvin.clamp(1000, 40000) as f32) / 100.0;
+        let r = if t <= 66.0 { 255.0 } else { 329.69873 * (t - 60.0).powf(-0.13320476) };
+        let g = if t <= 66.0 {
+            99.4708 * t.ln() - 161.11957
+        } else {
+            288.12216 * (t - 60.0).powf(-0.07551485)
+        };
+        let b = if t >= 66.0 {
+            255.0
+        } else if t <= 19.0 {
+            0.0
+        } else {
+            138.51773 * (t - 10.0).ln() - 305.0448
+        };
+        RGB8::new(
+            r.clamp(0.0, 255.0).round() as u8,
+            g.clamp(0.0, 255.0).round() as u8,
+            b.clamp(0.0, 255.0).round() as u8
+        )
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = (c as f32) / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    to_u8(if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 })
+}
+
+impl From<RGB8> for Oklab {
+    fn from(rgb: RGB8) -> Self {
+        let r = srgb_to_linear(rgb.r);
+        let g = srgb_to_linear(rgb.g);
+        let b = srgb_to_linear(rgb.b);
+        let l = (0.41222147 * r + 0.53633254 * g + 0.05144599 * b).cbrt();
+        let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+        let s = (0.08830246 * r + 0.28171884 * g + 0.6299787 * b).cbrt();
+        Oklab {
+            l: 0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+            a: 1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+            b: 0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+        }
+    }
+}
+
+impl From<Oklab> for RGB8 {
+    fn from(lab: Oklab) -> Self {
+        let l = (lab.l + 0.39633778 * lab.a + 0.21580376 * lab.b).powi(3);
+        let m = (lab.l - 0.105561346 * lab.a - 0.06385417 * lab.b).powi(3);
+        let s = (lab.l - 0.08948418 * lab.a - 1.2914855 * lab.b).powi(3);
+        RGB8::new(
+            linear_to_srgb(4.0767417 * l - 3.3077116 * m + 0.23096994 * s),
+            linear_to_srgb(-1.268438 * l + 2.6097574 * m - 0.34131938 * s),
+            linear_to_srgb(-0.0041960863 * l - 0.7034186 * m + 1.7076147 * s)
+        )
+    }
+}
+
+/// 在sRGB空间中线性插值，`t`为0-1
+pub fn lerp_rgb(from: RGB8, to: RGB8, t: f32) -> RGB8 {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| ((a as f32) + ((b as f32) - (a as f32)) * t).round() as u8;
+    RGB8::new(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+/// 在Oklab感知色彩空间中插值，`t`为0-1，渐变过程中亮度变化更自然
+pub fn lerp_oklab(from: RGB8, to: RGB8, t: f32) -> RGB8 {
+    let t = t.clamp(0.0, 1.0);
+    if t == 0.0 {
+        return from;
+    }
+    if t == 1.0 {
+        return to;
+    }
+    let a = Oklab::from(from);
+    let b = Oklab::from(to);
+    Oklab {
+        l: a.l + (b.l - a.l) * t,
+        a: a.a + (b.a - a.a) * t,
+        b: a.b + (b.b - a.b) * t,
+    }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: RGB8, b: RGB8) -> bool {
+        let d = |x: u8, y: u8| (x as i16 - y as i16).abs() <= 1;
+        d(a.r, b.r) && d(a.g, b.g) && d(a.b, b.b)
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_eq!(RGB8::from(Hsv::new(0.0, 1.0, 1.0)), RGB8::new(255, 0, 0));
+        assert_eq!(RGB8::from(Hsv::new(120.0, 1.0, 1.0)), RGB8::new(0, 255, 0));
+        assert_eq!(RGB8::from(Hsv::new(240.0, 1.0, 1.0)), RGB8::new(0, 0, 255));
+        assert_eq!(RGB8::from(Hsv::new(60.0, 1.0, 1.0)), RGB8::new(255, 255, 0));
+        assert_eq!(RGB8::from(Hsv::new(360.0, 1.0, 1.0)), RGB8::new(255, 0, 0));
+        assert_eq!(RGB8::from(Hsv::new(-120.0, 1.0, 1.0)), RGB8::new(0, 0, 255));
+        assert_eq!(RGB8::from(Hsv::new(200.0, 0.0, 0.5)), RGB8::new(128, 128, 128));
+        assert_eq!(RGB8::from(Hsv::new(0.0, 1.0, 0.0)), RGB8::new(0, 0, 0));
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for rgb in [
+            RGB8::new(255, 128, 0),
+            RGB8::new(12, 200, 99),
+            RGB8::new(70, 70, 200),
+            RGB8::new(250, 10, 180),
+            RGB8::new(33, 33, 33),
+        ] {
+            let hsv = Hsv::from(rgb);
+            assert!(close(RGB8::from(hsv), rgb), "{rgb:?} -> {hsv:?}");
+        }
+    }
+
+    #[test]
+    fn hsl_matches_hsv() {
+        assert_eq!(RGB8::from(Hsl::new(0.0, 1.0, 0.5)), RGB8::new(255, 0, 0));
+        assert_eq!(RGB8::from(Hsl::new(240.0, 1.0, 0.25)), RGB8::new(0, 0, 128));
+        assert_eq!(RGB8::from(Hsl::new(0.0, 0.0, 1.0)), RGB8::new(255, 255, 255));
+        assert_eq!(RGB8::from(Hsl::new(0.0, 1.0, 0.0)), RGB8::new(0, 0, 0));
+        assert!(close(RGB8::from(Hsl::new(30.0, 1.0, 0.5)), Hsv::new(30.0, 1.0, 1.0).into()));
+    }
+
+    #[test]
+    fn kelvin() {
+        // 6600K附近接近纯白
+        let daylight = RGB8::from(Cct::new(6600));
+        assert!(daylight.r == 255 && daylight.g > 240 && daylight.b > 240, "{daylight:?}");
+        // 暖白偏橙
+        let warm = RGB8::from(Cct::new(2700));
+        assert_eq!(warm.r, 255);
+        assert!(warm.g > warm.b && warm.b > 80, "{warm:?}");
+        // 烛光没有蓝色
+        let candle = RGB8::from(Cct::new(1000));
+        assert_eq!((candle.r, candle.b), (255, 0));
+        // 高色温偏蓝
+        let sky = RGB8::from(Cct::new(15000));
+        assert!(sky.b == 255 && sky.r < 200, "{sky:?}");
+        // 超出范围时被限制
+        assert_eq!(RGB8::from(Cct::new(100)), candle);
+    }
+
+    #[test]
+    fn oklab_round_trip() {
+        for rgb in [
+            RGB8::new(0, 0, 0),
+            RGB8::new(255, 255, 255),
+            RGB8::new(255, 0, 0),
+            RGB8::new(0, 255, 0),
+            RGB8::new(0, 0, 255),
+            RGB8::new(123, 45, 67),
+        ] {
+            assert!(close(Oklab::from(rgb).into(), rgb), "{rgb:?}");
+        }
+        let white = Oklab::from(RGB8::new(255, 255, 255));
+        assert!((white.l - 1.0).abs() < 1e-3 && white.a.abs() < 1e-3 && white.b.abs() < 1e-3);
+    }
+
+    #[test]
+    fn lerp_endpoints_and_midpoint() {
+        let red = RGB8::new(255, 0, 0);
+        let blue = RGB8::new(0, 0, 255);
+        assert_eq!(lerp_oklab(red, blue, 0.0), red);
+        assert_eq!(lerp_oklab(red, blue, 1.0), blue);
+        assert_eq!(lerp_oklab(red, blue, -1.0), red);
+        assert_eq!(lerp_rgb(red, blue, 0.5), RGB8::new(128, 0, 128));
+
+        // Oklab中黑白的中点（L = 0.5）对应sRGB约99的灰色
+        let mid = lerp_oklab(RGB8::new(0, 0, 0), RGB8::new(255, 255, 255), 0.5);
+        assert_eq!(mid.r, mid.g);
+        assert_eq!(mid.g, mid.b);
+        assert!((97..=101).contains(&mid.r), "{mid:?}");
+    }
+
+    #[test]
+    fn hsv_lerp_takes_short_way() {
+        let a = Hsv::new(350.0, 1.0, 1.0);
+        let b = Hsv::new(10.0, 1.0, 1.0);
+        let mid = a.lerp(&b, 0.5);
+        assert!(mid.h < 1e-3 || (360.0 - mid.h) < 1e-3, "{mid:?}");
+        assert_eq!(a.lerp(&b, 1.0).h, 10.0);
+    }
+}
@@ -0,0 +1,127 @@
+//! 前端请求
+//!
+//! HTTP和BLE收到的请求先解析成这里的结构体，再通过`EffectHandle`交给灯效线程，
+//! 这样前端的处理逻辑可以配合`SimulatedStrip`在主机上测试。
+use anyhow::Result;
+use serde::{ Deserialize, Serialize };
+
+use super::{ effect::{ EffectHandle, Marquee }, ColorSpec, Transition, RGB8 };
+
+/// 设置颜色的请求，`transition`默认500ms缓入缓出，`segment`为空时作用于所有分段
+///
+/// ```json
+/// { "color": { "kelvin": 2700 }, "transition": { "duration_ms": 1000 }, "segment": "left" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorRequest {
+    pub color: ColorSpec,
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default)]
+    pub segment: Option<String>,
+}
+
+impl ColorRequest {
+    pub fn apply(&self, handle: &EffectHandle) -> Result<()> {
+        let color = self.color.to_rgb();
+        match &self.segment {
+            Some(segment) => handle.fade_segment_to(segment, color, self.transition),
+            None => handle.fade_to(color, self.transition),
+        }
+    }
+}
+
+/// 设置亮度的请求，`segment`为空时设置全局亮度
+///
+/// ```json
+/// { "brightness": 128 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrightnessRequest {
+    pub brightness: u8,
+    #[serde(default)]
+    pub segment: Option<String>,
+}
+
+impl BrightnessRequest {
+    pub fn apply(&self, handle: &EffectHandle) -> Result<()> {
+        match &self.segment {
+            Some(segment) => handle.set_segment_brightness(segment, self.brightness),
+            None => handle.set_brightness(self.brightness),
+        }
+    }
+}
+
+/// 解析BLE颜色特性写入的数据：
+/// - 3个字节为原始RGB，使用默认过渡
+/// - 5个字节为RGB加上小端序u16的过渡时间（毫秒）
+/// - 其它为JSON格式的颜色（HSV、HSL或色温），使用默认过渡
+pub fn parse_color(data: &[u8]) -> Result<(RGB8, Transition)> {
+    match data {
+        [r, g, b] => Ok((RGB8::new(*r, *g, *b), Transition::default())),
+        [r, g, b, d0, d1] => {
+            let transition = Transition {
+                duration_ms: u16::from_le_bytes([*d0, *d1]) as u32,
+                ..Default::default()
+            };
+            Ok((RGB8::new(*r, *g, *b), transition))
+        }
+        _ => Ok((serde_json::from_slice::<ColorSpec>(data)?.to_rgb(), Transition::default())),
+    }
+}
+
+/// 解析BLE文字特性写入的数据：以`{`开头时为JSON格式的跑马灯参数，否则为UTF-8文本
+pub fn parse_marquee(data: &[u8]) -> Result<Marquee> {
+    if data.first() == Some(&b'{') {
+        return Ok(serde_json::from_slice(data)?);
+    }
+    Ok(Marquee {
+        text: String::from_utf8(data.to_vec())?,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::led::{ effect::EffectRunner, Segment, SimulatedStrip };
+
+    #[test]
+    fn parse_raw_color() {
+        let (color, transition) = parse_color(&[1, 2, 3]).unwrap();
+        assert_eq!(color, RGB8::new(1, 2, 3));
+        assert_eq!(transition, Transition::default());
+
+        let (color, transition) = parse_color(&[1, 2, 3, 0xe8, 0x03]).unwrap();
+        assert_eq!(color, RGB8::new(1, 2, 3));
+        assert_eq!(transition.duration_ms, 1000);
+    }
+
+    #[test]
+    fn parse_plain_text() {
+        let marquee = parse_marquee("Hi".as_bytes()).unwrap();
+        assert_eq!(marquee.text, "Hi");
+        assert!(parse_marquee(&[0xff, 0xfe]).is_err());
+    }
+
+    #[test]
+    fn color_request_targets_segment() {
+        let (mut runner, handle) = EffectRunner::new(SimulatedStrip::new(4), 30);
+        let log = runner.strip().log();
+        handle.set_segments(vec![Segment::new("a", 0, 2), Segment::new("b", 2, 2)]).unwrap();
+        let request = ColorRequest {
+            color: ColorSpec::Rgb(RGB8::new(0, 0, 255)),
+            transition: Transition::instant(),
+            segment: Some("b".into()),
+        };
+        request.apply(&handle).unwrap();
+        BrightnessRequest { brightness: 128, segment: None }.apply(&handle).unwrap();
+        runner.step(Instant::now());
+
+        // WS2812B按GRB的顺序发送，全局亮度在帧处理阶段生效
+        let bytes = log.last().unwrap().bytes;
+        assert_eq!(bytes, [0, 0, 0, 0, 0, 0, 0, 0, 128, 0, 0, 128]);
+    }
+}
@@ -0,0 +1,398 @@
+//! 内置灯效
+//!
+//! 每个灯效的结构体本身就是它的参数，运行时状态用`#[serde(skip)]`排除在配置之外。
+use serde::{ Deserialize, Serialize };
+
+use super::{ cycle_value_sin, scale_rgb, wheel, Effect, Rng, Tick };
+use crate::led::RGB8;
+
+const WHITE: RGB8 = RGB8::new(255, 255, 255);
+const WARM: RGB8 = RGB8::new(255, 147, 41);
+
+/// 纯色
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Solid {
+    pub color: RGB8,
+}
+
+impl Effect for Solid {
+    fn render(&mut self, _tick: &Tick, pixels: &mut [RGB8]) {
+        pixels.fill(self.color);
+    }
+}
+
+/// 呼吸灯：颜色按正弦曲线周期性地变亮变暗
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Breathe {
+    pub color: RGB8,
+    /// 一次完整呼吸的周期（毫秒）
+    pub period_ms: u32,
+}
+
+impl Default for Breathe {
+    fn default() -> Self {
+        Self { color: WARM, period_ms: 3000 }
+    }
+}
+
+impl Effect for Breathe {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let period = self.period_ms.max(1) as u128;
+        let phase = ((tick.elapsed.as_millis() % period) as f32) / (period as f32);
+        // 从最暗开始：sin(-π/2) = -1
+        let level = cycle_value_sin(phase * 2.0 - 0.5);
+        pixels.fill(scale_rgb(self.color, (level * 255.0) as u8));
+    }
+}
+
+/// 彩虹：整条灯带铺满色轮并随时间流动
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rainbow {
+    /// 色轮转一圈的时间（毫秒）
+    pub cycle_ms: u32,
+}
+
+impl Default for Rainbow {
+    fn default() -> Self {
+        Self { cycle_ms: 5000 }
+    }
+}
+
+impl Effect for Rainbow {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let cycle = self.cycle_ms.max(1) as u128;
+        let offset = ((tick.elapsed.as_millis() % cycle) * 256) / cycle;
+        let len = pixels.len().max(1);
+        for (i, p) in pixels.iter_mut().enumerate() {
+            *p = wheel((offset as usize + (i * 256) / len) as u8);
+        }
+    }
+}
+
+/// 颜色擦除：逐颗点亮，全部点亮后再逐颗熄灭
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorWipe {
+    pub color: RGB8,
+    /// 每颗灯珠之间的间隔（毫秒）
+    pub interval_ms: u32,
+}
+
+impl Default for ColorWipe {
+    fn default() -> Self {
+        Self { color: RGB8::new(0, 0, 255), interval_ms: 50 }
+    }
+}
+
+impl Effect for ColorWipe {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let len = pixels.len();
+        if len == 0 {
+            return;
+        }
+        let step = (tick.elapsed.as_millis() / (self.interval_ms.max(1) as u128)) as usize;
+        let step = step % (len * 2);
+        for (i, p) in pixels.iter_mut().enumerate() {
+            let on = if step < len { i <= step } else { i > step - len };
+            *p = if on { self.color } else { RGB8::default() };
+        }
+    }
+}
+
+/// 剧场追逐：每隔两颗点亮一颗，并向前移动
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TheaterChase {
+    pub color: RGB8,
+    /// 每次移动的间隔（毫秒）
+    pub interval_ms: u32,
+}
+
+impl Default for TheaterChase {
+    fn default() -> Self {
+        Self { color: WHITE, interval_ms: 100 }
+    }
+}
+
+impl Effect for TheaterChase {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let offset = (tick.elapsed.as_millis() / (self.interval_ms.max(1) as u128)) % 3;
+        for (i, p) in pixels.iter_mut().enumerate() {
+            let on = ((i as u128) + 3 - offset) % 3 == 0;
+            *p = if on { self.color } else { RGB8::default() };
+        }
+    }
+}
+
+/// 火焰：经典的Fire2012算法，灯带起点为火焰底部
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fire {
+    /// 冷却速度，越大火焰越短
+    pub cooling: u8,
+    /// 每帧产生火花的概率（0-255）
+    pub sparking: u8,
+    #[serde(skip)]
+    heat: Vec<u8>,
+    #[serde(skip)]
+    rng: Rng,
+}
+
+impl Default for Fire {
+    fn default() -> Self {
+        Self { cooling: 55, sparking: 120, heat: Vec::new(), rng: Rng::default() }
+    }
+}
+
+impl Fire {
+    fn heat_color(heat: u8) -> RGB8 {
+        let t192 = crate::led::processing::scale(heat, 191);
+        let ramp = (t192 & 0x3f) << 2;
+        if t192 & 0x80 != 0 {
+            RGB8::new(255, 255, ramp)
+        } else if t192 & 0x40 != 0 {
+            RGB8::new(255, ramp, 0)
+        } else {
+            RGB8::new(ramp, 0, 0)
+        }
+    }
+}
+
+impl Effect for Fire {
+    fn render(&mut self, _tick: &Tick, pixels: &mut [RGB8]) {
+        let len = pixels.len();
+        self.heat.resize(len, 0);
+
+        // 每个单元冷却一点
+        let max_cool = ((self.cooling as u32) * 10) / (len.max(1) as u32) + 2;
+        for h in self.heat.iter_mut() {
+            *h = h.saturating_sub(self.rng.range(0, max_cool) as u8);
+        }
+        // 热量向上飘散
+        for k in (2..len).rev() {
+            let sum = (self.heat[k - 1] as u16) + (self.heat[k - 2] as u16) * 2;
+            self.heat[k] = (sum / 3) as u8;
+        }
+        // 在底部随机点燃火花
+        if len > 0 && self.rng.next_u8() < self.sparking {
+            let y = (self.rng.range(0, 7) as usize).min(len - 1);
+            self.heat[y] = self.heat[y].saturating_add(self.rng.range(160, 255) as u8);
+        }
+
+        for (p, h) in pixels.iter_mut().zip(&self.heat) {
+            *p = Self::heat_color(*h);
+        }
+    }
+}
+
+/// 闪烁：随机点亮灯珠后慢慢熄灭
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Twinkle {
+    pub color: RGB8,
+    /// 每帧点亮一颗新灯珠的概率（0-255）
+    pub density: u8,
+    /// 每帧亮度衰减量
+    pub fade: u8,
+    #[serde(skip)]
+    levels: Vec<u8>,
+    #[serde(skip)]
+    rng: Rng,
+}
+
+impl Default for Twinkle {
+    fn default() -> Self {
+        Self { color: WHITE, density: 80, fade: 8, levels: Vec::new(), rng: Rng::default() }
+    }
+}
+
+impl Effect for Twinkle {
+    fn render(&mut self, _tick: &Tick, pixels: &mut [RGB8]) {
+        let len = pixels.len();
+        self.levels.resize(len, 0);
+        for level in self.levels.iter_mut() {
+            *level = level.saturating_sub(self.fade);
+        }
+        if len > 0 && self.rng.next_u8() < self.density {
+            let i = self.rng.range(0, len as u32) as usize;
+            self.levels[i] = 255;
+        }
+        for (p, level) in pixels.iter_mut().zip(&self.levels) {
+            *p = scale_rgb(self.color, *level);
+        }
+    }
+}
+
+/// 彗星：一个亮点带着渐暗的尾巴沿灯带移动
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Comet {
+    pub color: RGB8,
+    /// 尾巴长度（灯珠数）
+    pub tail: u16,
+    /// 每移动一颗的间隔（毫秒）
+    pub interval_ms: u32,
+}
+
+impl Default for Comet {
+    fn default() -> Self {
+        Self { color: RGB8::new(0, 255, 255), tail: 6, interval_ms: 40 }
+    }
+}
+
+impl Effect for Comet {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let tail = self.tail.max(1) as usize;
+        let span = pixels.len() + tail;
+        let head = ((tick.elapsed.as_millis() / (self.interval_ms.max(1) as u128)) as usize) % span;
+        for (i, p) in pixels.iter_mut().enumerate() {
+            *p = match head.checked_sub(i) {
+                Some(d) if d < tail => {
+                    scale_rgb(self.color, (((tail - d) * 255) / tail) as u8)
+                }
+                _ => RGB8::default(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::led::{ sim::SimulatedStrip, writer::SmartLedWriter, RGBW8 };
+
+    const RED: RGB8 = RGB8::new(200, 100, 0);
+    const OFF: RGB8 = RGB8::new(0, 0, 0);
+
+    fn tick(frame: u32, ms: u64) -> Tick {
+        Tick { frame, elapsed: Duration::from_millis(ms) }
+    }
+
+    /// 渲染`ms`毫秒时的一帧
+    fn render(effect: &mut impl Effect, len: usize, ms: u64) -> Vec<RGB8> {
+        let mut pixels = vec![RGB8::new(1, 2, 3); len];
+        effect.render(&tick(0, ms), &mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn solid_fills_every_pixel() {
+        let mut solid = Solid { color: RED };
+        assert_eq!(render(&mut solid, 3, 0), [RED; 3]);
+        assert_eq!(render(&mut solid, 3, 10_000), [RED; 3]);
+        assert!(render(&mut solid, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn breathe_follows_period() {
+        let mut breathe = Breathe { color: RED, period_ms: 1000 };
+        // 从最暗开始，半个周期时最亮，一个周期后回到最暗
+        assert_eq!(render(&mut breathe, 2, 0), [OFF; 2]);
+        assert_eq!(render(&mut breathe, 1, 250), [RGB8::new(100, 50, 0)]);
+        let brightest = render(&mut breathe, 1, 500)[0];
+        assert!(brightest.r >= 199 && brightest.g >= 99, "{:?}", brightest);
+        assert_eq!(render(&mut breathe, 1, 1000), [OFF]);
+        assert_eq!(render(&mut breathe, 1, 1250), render(&mut breathe, 1, 250));
+    }
+
+    #[test]
+    fn rainbow_spreads_and_flows() {
+        let mut rainbow = Rainbow { cycle_ms: 1000 };
+        let start = render(&mut rainbow, 4, 0);
+        assert_eq!(start, [wheel(0), wheel(64), wheel(128), wheel(192)]);
+        // 四分之一周期后整体移动一颗
+        assert_eq!(&render(&mut rainbow, 4, 250)[..3], &start[1..]);
+        assert_eq!(render(&mut rainbow, 4, 1000), start);
+    }
+
+    #[test]
+    fn color_wipe_fills_then_clears() {
+        let mut wipe = ColorWipe { color: RED, interval_ms: 10 };
+        let frames = [0, 10, 20, 30, 40, 50, 60].map(|ms| render(&mut wipe, 3, ms));
+        assert_eq!(frames[0], [RED, OFF, OFF]);
+        assert_eq!(frames[1], [RED, RED, OFF]);
+        assert_eq!(frames[2], [RED; 3]);
+        assert_eq!(frames[3], [OFF, RED, RED]);
+        assert_eq!(frames[4], [OFF, OFF, RED]);
+        assert_eq!(frames[5], [OFF; 3]);
+        // 两倍灯珠数的步数后重新开始
+        assert_eq!(frames[6], frames[0]);
+        assert!(render(&mut wipe, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn theater_chase_moves_every_interval() {
+        let mut chase = TheaterChase { color: RED, interval_ms: 10 };
+        assert_eq!(render(&mut chase, 6, 0), [RED, OFF, OFF, RED, OFF, OFF]);
+        assert_eq!(render(&mut chase, 6, 15), [OFF, RED, OFF, OFF, RED, OFF]);
+        assert_eq!(render(&mut chase, 6, 20), [OFF, OFF, RED, OFF, OFF, RED]);
+        assert_eq!(render(&mut chase, 6, 30), render(&mut chase, 6, 0));
+    }
+
+    #[test]
+    fn fire_stays_dark_without_sparks() {
+        let mut fire = Fire { sparking: 0, ..Default::default() };
+        for frame in 0..10 {
+            assert_eq!(render(&mut fire, 8, frame * 33), [OFF; 8]);
+        }
+        assert!(render(&mut fire, 0, 0).is_empty());
+
+        // 调色板从黑到红、黄再到接近白色
+        assert_eq!(Fire::heat_color(0), OFF);
+        assert_eq!(Fire::heat_color(255), RGB8::new(255, 255, 252));
+        let mut fire = Fire { sparking: 255, ..Default::default() };
+        let pixels = (0..20).map(|frame| render(&mut fire, 8, frame * 33)).last().unwrap();
+        assert!(pixels[..3].iter().any(|p| *p != OFF));
+        assert!(pixels.iter().all(|p| p.r >= p.g && p.g >= p.b));
+    }
+
+    #[test]
+    fn twinkle_lights_and_fades() {
+        let mut twinkle = Twinkle { color: RED, density: 255, fade: 255, ..Default::default() };
+        for frame in 0..5 {
+            let pixels = render(&mut twinkle, 8, frame * 33);
+            // 每帧点亮一颗，上一颗在同一帧完全熄灭
+            assert_eq!(pixels.iter().filter(|p| **p == RED).count(), 1);
+            assert_eq!(pixels.iter().filter(|p| **p == OFF).count(), 7);
+        }
+        let mut dark = Twinkle { density: 0, ..Default::default() };
+        assert_eq!(render(&mut dark, 4, 0), [OFF; 4]);
+    }
+
+    #[test]
+    fn comet_tail_fades_and_wraps() {
+        let mut comet = Comet { color: RED, tail: 2, interval_ms: 10 };
+        let half = scale_rgb(RED, 127);
+        assert_eq!(render(&mut comet, 4, 0), [RED, OFF, OFF, OFF]);
+        assert_eq!(render(&mut comet, 4, 10), [half, RED, OFF, OFF]);
+        // 彗头离开灯带后尾巴还留在末端
+        assert_eq!(render(&mut comet, 4, 40), [OFF, OFF, OFF, half]);
+        assert_eq!(render(&mut comet, 4, 50), [OFF; 4]);
+        // 灯珠数加尾巴长度后重新开始
+        assert_eq!(render(&mut comet, 4, 60), render(&mut comet, 4, 0));
+    }
+
+    #[test]
+    fn frames_reach_the_strip() {
+        let mut strip = SimulatedStrip::new(3);
+        let log = strip.log();
+        let mut wipe = ColorWipe { color: RED, interval_ms: 10 };
+        let mut pixels = vec![RGB8::default(); strip.len()];
+        for frame in 0..8 {
+            wipe.render(&tick(frame, (frame as u64) * 10), &mut pixels);
+            strip.write(pixels.iter().copied()).unwrap();
+        }
+
+        let frames = log.frames();
+        assert_eq!(frames.len(), 8);
+        assert_eq!(frames[2].pixels, [RGBW8::from(RED); 3]);
+        // WS2812B按GRB发送
+        assert_eq!(frames[0].bytes, [100, 200, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(frames[6].pixels, frames[0].pixels);
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{ Deserialize, Serialize };
+
+use super::{ Effect, Tick };
+use crate::led::{ font, layout::{ MatrixLayout, MatrixView }, RGB8 };
+
+/// 跑马灯：在点阵上从右向左滚动显示文字
+///
+/// `speed`不大于0时文字从最左边开始静止显示，超出点阵的部分被裁剪。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Marquee {
+    pub text: String,
+    pub color: RGB8,
+    /// 滚动速度（列/秒），不大于0时不滚动
+    pub speed: f32,
+    /// 点阵布局，默认是32x8的蛇形点阵
+    pub layout: MatrixLayout,
+}
+
+impl Default for Marquee {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            color: RGB8::new(255, 255, 255),
+            speed: 10.0,
+            layout: MatrixLayout::new(32, 8),
+        }
+    }
+}
+
+impl Effect for Marquee {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let mut view = MatrixView::new(self.layout, pixels);
+        view.fill(RGB8::default());
+
+        let width = view.width() as i32;
+        let text_width = font::text_width(&self.text);
+        // 垂直居中
+        let y = ((view.height() as i32) - font::CHAR_HEIGHT).max(0) / 2;
+
+        let x = if self.speed <= 0.0 {
+            0
+        } else {
+            // 文字从右边完全移进来，再从左边完全移出去
+            let span = text_width + width;
+            let offset = (tick.elapsed.as_secs_f32() * self.speed) as i32;
+            width - offset.rem_euclid(span.max(1))
+        };
+        font::draw_text(&mut view, x, y, &self.text, self.color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::led::layout::Wiring;
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+
+    /// 渲染`secs`秒时的画面，返回每一列是否有点亮的像素
+    fn lit_columns(marquee: &mut Marquee, secs: u64) -> Vec<bool> {
+        let mut pixels = vec![RGB8::default(); marquee.layout.len()];
+        marquee.render(&(Tick { frame: 0, elapsed: Duration::from_secs(secs) }), &mut pixels);
+        let view = MatrixView::new(marquee.layout, &mut pixels);
+        (0..view.width())
+            .map(|x| (0..view.height()).any(|y| view.get(x, y) != Some(RGB8::default())))
+            .collect()
+    }
+
+    fn marquee(text: &str, speed: f32) -> Marquee {
+        Marquee {
+            text: text.into(),
+            color: RED,
+            speed,
+            layout: MatrixLayout::new(8, 8).wiring(Wiring::Progressive),
+        }
+    }
+
+    #[test]
+    fn static_when_speed_is_not_positive() {
+        // 'I'只有中间三列有像素
+        let expected = [false, true, true, true, false, false, false, false];
+        for speed in [0.0, -1.0] {
+            assert_eq!(lit_columns(&mut marquee("I", speed), 0), expected);
+            assert_eq!(lit_columns(&mut marquee("I", speed), 100), expected);
+        }
+        // 放不下的文字也静止显示，只是被裁剪
+        let lit = lit_columns(&mut marquee("HELLO", 0.0), 3);
+        assert!(lit[0] && lit[7]);
+    }
+
+    #[test]
+    fn scrolls_from_right_to_left() {
+        let mut m = marquee("I", 1.0);
+        // 刚开始文字在点阵右边之外
+        assert!(lit_columns(&mut m, 0).iter().all(|lit| !lit));
+        // 5秒后文字左边缘在第3列
+        let at_5 = [false, false, false, false, true, true, true, false];
+        assert_eq!(lit_columns(&mut m, 5), at_5);
+        // 一个周期是文字宽度加点阵宽度：5 + 8 = 13列
+        assert!(lit_columns(&mut m, 13).iter().all(|lit| !lit));
+        assert_eq!(lit_columns(&mut m, 18), at_5);
+    }
+}
@@ -0,0 +1,172 @@
+//! 灯效引擎
+//!
+//! `Effect`负责把第N帧渲染到像素缓冲里，`EffectConfig`描述灯效及其参数，
+//! 可以直接从HTTP/BLE的JSON反序列化，在运行时切换。
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{ Deserialize, Serialize };
+
+use super::RGB8;
+
+mod builtin;
+mod marquee;
+mod runner;
+pub use builtin::*;
+pub use marquee::Marquee;
+pub use runner::{ EffectHandle, EffectRunner };
+
+/// 渲染一帧时的时间信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tick {
+    /// 从灯效开始到现在的帧序号
+    pub frame: u32,
+    /// 从灯效开始到现在经过的时间
+    pub elapsed: Duration,
+}
+
+/// 灯效：把第`tick.frame`帧渲染到`pixels`中
+pub trait Effect: Send {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]);
+}
+
+/// 灯效及其参数，`effect`字段指定灯效类型，其余字段为参数
+///
+/// ```json
+/// { "effect": "breathe", "color": { "r": 255, "g": 0, "b": 0 }, "period_ms": 3000 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum EffectConfig {
+    Solid(Solid),
+    Breathe(Breathe),
+    Rainbow(Rainbow),
+    ColorWipe(ColorWipe),
+    TheaterChase(TheaterChase),
+    Fire(Fire),
+    Twinkle(Twinkle),
+    Comet(Comet),
+    Marquee(Marquee),
+}
+
+/// 前端切换灯效的请求，`segment`为空时作用于所有分段
+///
+/// ```json
+/// { "segment": "left", "effect": "fire", "cooling": 60 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectRequest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub segment: Option<String>,
+    #[serde(flatten)]
+    pub effect: EffectConfig,
+}
+
+impl EffectRequest {
+    pub fn apply(&self, handle: &EffectHandle) -> Result<()> {
+        match &self.segment {
+            Some(segment) => handle.set_segment_effect(segment, self.effect.clone()),
+            None => handle.set_effect(self.effect.clone()),
+        }
+    }
+}
+
+impl EffectConfig {
+    /// 根据配置创建一个新的灯效实例
+    pub fn build(&self) -> Box<dyn Effect> {
+        match self.clone() {
+            EffectConfig::Solid(e) => Box::new(e),
+            EffectConfig::Breathe(e) => Box::new(e),
+            EffectConfig::Rainbow(e) => Box::new(e),
+            EffectConfig::ColorWipe(e) => Box::new(e),
+            EffectConfig::TheaterChase(e) => Box::new(e),
+            EffectConfig::Fire(e) => Box::new(e),
+            EffectConfig::Twinkle(e) => Box::new(e),
+            EffectConfig::Comet(e) => Box::new(e),
+            EffectConfig::Marquee(e) => Box::new(e),
+        }
+    }
+
+    /// 使用默认参数的所有内置灯效，按钮可以依次切换
+    pub fn presets() -> Vec<EffectConfig> {
+        vec![
+            EffectConfig::Breathe(Breathe::default()),
+            EffectConfig::Rainbow(Rainbow::default()),
+            EffectConfig::ColorWipe(ColorWipe::default()),
+            EffectConfig::TheaterChase(TheaterChase::default()),
+            EffectConfig::Fire(Fire::default()),
+            EffectConfig::Twinkle(Twinkle::default()),
+            EffectConfig::Comet(Comet::default()),
+        ]
+    }
+}
+
+impl Default for EffectConfig {
+    fn default() -> Self {
+        EffectConfig::Solid(Solid::default())
+    }
+}
+
+/// sin周期变化，返回值在0到1之间
+pub fn cycle_value_sin(t: f32) -> f32 {
+    ((t * std::f32::consts::PI).sin() + 1.0) / 2.0
+}
+
+/// 色轮：把0-255的色相映射到RGB
+pub fn wheel(hue: u8) -> RGB8 {
+    let hue = 255 - hue;
+    match hue {
+        0..=84 => RGB8::new(255 - hue * 3, 0, hue * 3),
+        85..=169 => {
+            let hue = hue - 85;
+            RGB8::new(0, hue * 3, 255 - hue * 3)
+        }
+        _ => {
+            let hue = hue - 170;
+            RGB8::new(hue * 3, 255 - hue * 3, 0)
+        }
+    }
+}
+
+/// 按比例缩放颜色，255表示保持不变
+pub fn scale_rgb(color: RGB8, factor: u8) -> RGB8 {
+    use super::processing::scale;
+    RGB8::new(scale(color.r, factor), scale(color.g, factor), scale(color.b, factor))
+}
+
+/// 灯效使用的简单伪随机数发生器（xorshift32）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Self(if seed == 0 { 0x1234_5678 } else { seed })
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u32() >> 24) as u8
+    }
+
+    /// 返回`[low, high)`范围内的随机数
+    pub fn range(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u32() % (high - low))
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
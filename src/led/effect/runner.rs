@@ -0,0 +1,421 @@
+use std::{
+    sync::{ mpsc::{ channel, Receiver, Sender, TryRecvError }, Arc, Mutex },
+    time::{ Duration, Instant },
+};
+
+use anyhow::{ anyhow, bail, Result };
+
+use super::{ scale_rgb, Effect, EffectConfig, Solid, Tick };
+use crate::led::{
+    layout::Segment,
+    transition::{ Fade, Transition },
+    writer::SmartLedWriter,
+    Scene,
+    RGB8,
+};
+
+/// 发送给灯效线程的命令，`None`表示作用于所有分段
+enum Command {
+    Effect(Option<String>, EffectConfig),
+    Fade(Option<String>, RGB8, Transition),
+    Brightness(u8),
+    SegmentBrightness(String, u8),
+    Segments(Vec<Segment>),
+}
+
+/// 灯效线程的控制句柄，可以克隆后交给HTTP、BLE、按钮等前端使用
+#[derive(Clone)]
+pub struct EffectHandle {
+    tx: Sender<Command>,
+    len: usize,
+    // 作用于整条灯带的最近一次灯效和亮度，用来保存场景
+    scene: Arc<Mutex<Scene>>,
+}
+
+impl EffectHandle {
+    /// 灯带长度
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 切换所有分段的灯效，下一帧生效
+    pub fn set_effect(&self, effect: EffectConfig) -> Result<()> {
+        self.send(Command::Effect(None, effect.clone()))?;
+        self.update_scene(|scene| {
+            scene.effect = effect;
+        });
+        Ok(())
+    }
+
+    /// 切换指定分段的灯效
+    pub fn set_segment_effect(&self, segment: &str, effect: EffectConfig) -> Result<()> {
+        self.send(Command::Effect(Some(segment.to_string()), effect))
+    }
+
+    /// 以默认的过渡（500ms）渐变到纯色
+    pub fn set_color(&self, color: RGB8) -> Result<()> {
+        self.fade_to(color, Transition::default())
+    }
+
+    /// 按照给定的过渡渐变到纯色，渐变过程中收到新颜色时从当前显示的颜色重新开始
+    pub fn fade_to(&self, color: RGB8, transition: Transition) -> Result<()> {
+        self.send(Command::Fade(None, color, transition))?;
+        self.update_scene(|scene| {
+            scene.effect = EffectConfig::Solid(Solid { color });
+        });
+        Ok(())
+    }
+
+    /// 指定分段渐变到纯色
+    pub fn fade_segment_to(&self, segment: &str, color: RGB8, transition: Transition) -> Result<()> {
+        self.send(Command::Fade(Some(segment.to_string()), color, transition))
+    }
+
+    /// 设置全局亮度（0-255）
+    pub fn set_brightness(&self, brightness: u8) -> Result<()> {
+        self.send(Command::Brightness(brightness))?;
+        self.update_scene(|scene| {
+            scene.brightness = brightness;
+        });
+        Ok(())
+    }
+
+    /// 设置分段亮度（0-255），与全局亮度叠加
+    pub fn set_segment_brightness(&self, segment: &str, brightness: u8) -> Result<()> {
+        self.send(Command::SegmentBrightness(segment.to_string(), brightness))
+    }
+
+    /// 重新划分分段，所有分段都从纯黑开始
+    pub fn set_segments(&self, segments: Vec<Segment>) -> Result<()> {
+        Segment::validate(&segments, self.len)?;
+        self.send(Command::Segments(segments))
+    }
+
+    /// 渐变到熄灭
+    pub fn off(&self) -> Result<()> {
+        self.set_color(RGB8::default())
+    }
+
+    /// 当前整条灯带的灯效和亮度，分段各自的灯效不包含在内
+    pub fn scene(&self) -> Scene {
+        self.scene.lock().unwrap().clone()
+    }
+
+    /// 恢复场景，纯色场景以默认过渡渐变过去
+    pub fn apply_scene(&self, scene: &Scene) -> Result<()> {
+        self.set_brightness(scene.brightness)?;
+        match &scene.effect {
+            EffectConfig::Solid(solid) => self.set_color(solid.color),
+            effect => self.set_effect(effect.clone()),
+        }
+    }
+
+    fn update_scene(&self, f: impl FnOnce(&mut Scene)) {
+        f(&mut self.scene.lock().unwrap());
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.tx.send(command).map_err(|_| anyhow!("effect runner stopped"))
+    }
+}
+
+/// 一个分段及其正在运行的灯效
+struct Slot {
+    segment: Segment,
+    effect: Box<dyn Effect>,
+    brightness: u8,
+    started: Instant,
+    frame: u32,
+}
+
+impl Slot {
+    fn new(segment: Segment, now: Instant) -> Self {
+        Self {
+            segment,
+            effect: EffectConfig::default().build(),
+            brightness: 255,
+            started: now,
+            frame: 0,
+        }
+    }
+
+    fn start(&mut self, effect: Box<dyn Effect>, now: Instant) {
+        self.effect = effect;
+        self.started = now;
+        self.frame = 0;
+    }
+
+    fn render(&mut self, strip: &mut [RGB8], now: Instant) {
+        let tick = Tick { frame: self.frame, elapsed: now.saturating_duration_since(self.started) };
+        let pixels = self.segment.pixels(strip);
+        self.effect.render(&tick, pixels);
+        if self.brightness != 255 {
+            pixels.iter_mut().for_each(|p| {
+                *p = scale_rgb(*p, self.brightness);
+            });
+        }
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+/// 以固定帧率驱动灯效
+///
+/// 通常用`spawn`放到后台线程里运行；测试时可以用`new`创建后手动调用`step`，
+/// 配合`SimulatedStrip`逐帧检查输出。
+pub struct EffectRunner<W> {
+    strip: W,
+    rx: Receiver<Command>,
+    interval: Duration,
+    slots: Vec<Slot>,
+    pixels: Vec<RGB8>,
+    last: Vec<RGB8>,
+}
+
+impl<W: SmartLedWriter> EffectRunner<W> {
+    /// 接管灯带，返回灯效驱动和控制句柄
+    ///
+    /// 初始时只有一个覆盖整条灯带、名为`all`的分段。
+    pub fn new(strip: W, fps: u32) -> (Self, EffectHandle) {
+        let (tx, rx) = channel();
+        let len = strip.len();
+        let runner = Self {
+            strip,
+            rx,
+            interval: Duration::from_micros(1_000_000 / (fps.max(1) as u64)),
+            slots: vec![Slot::new(Segment::whole(len), Instant::now())],
+            pixels: vec![RGB8::default(); len],
+            last: Vec::new(),
+        };
+        let handle = EffectHandle { tx, len, scene: Arc::new(Mutex::new(Scene::default())) };
+        (runner, handle)
+    }
+
+    /// 接管灯带并启动灯效线程，返回控制句柄
+    pub fn spawn(strip: W, fps: u32) -> Result<EffectHandle> where W: 'static {
+        let (mut runner, handle) = Self::new(strip, fps);
+        std::thread::Builder
+            ::new()
+            .name("led-effect".into())
+            .stack_size(8192)
+            .spawn(move || runner.run())?;
+        Ok(handle)
+    }
+
+    /// 灯带
+    pub fn strip(&self) -> &W {
+        &self.strip
+    }
+
+    /// 上一帧渲染出的像素（分段亮度之后、全局亮度之前）
+    pub fn pixels(&self) -> &[RGB8] {
+        &self.pixels
+    }
+
+    /// 处理前端发来的所有命令，再渲染`now`时刻的一帧
+    ///
+    /// 返回`false`表示句柄已经全部被丢弃。
+    pub fn step(&mut self, now: Instant) -> bool {
+        loop {
+            match self.rx.try_recv() {
+                Ok(command) => {
+                    if let Err(e) = self.apply(command, now) {
+                        log::error!("Failed to apply effect command: {}", e);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+
+        if let Err(e) = self.render_frame(now) {
+            log::error!("Failed to render effect frame: {}", e);
+        }
+        true
+    }
+
+    fn run(&mut self) {
+        let mut next = Instant::now();
+        while self.step(Instant::now()) {
+            next += self.interval;
+            let now = Instant::now();
+            if next > now {
+                std::thread::sleep(next - now);
+            } else {
+                // 渲染跟不上帧率时不去追赶，直接从现在重新计时
+                next = now;
+            }
+        }
+    }
+
+    /// 找到命令作用的分段，`None`表示所有分段
+    fn slots_mut(&mut self, segment: Option<&str>) -> Result<Vec<&mut Slot>> {
+        let slots = self.slots
+            .iter_mut()
+            .filter(|slot| segment.map_or(true, |name| slot.segment.name == name))
+            .collect::<Vec<_>>();
+        if slots.is_empty() {
+            bail!("segment {:?} not found", segment);
+        }
+        Ok(slots)
+    }
+
+    fn apply(&mut self, command: Command, now: Instant) -> Result<()> {
+        match command {
+            Command::Effect(segment, effect) => {
+                log::info!("Switch effect of {:?} to {:?}", segment, effect);
+                for slot in self.slots_mut(segment.as_deref())? {
+                    slot.start(effect.build(), now);
+                }
+            }
+            Command::Fade(segment, color, transition) => {
+                log::info!("Fade {:?} to {:?} over {}ms", segment, color, transition.duration_ms);
+                let pixels = self.pixels.clone();
+                for slot in self.slots_mut(segment.as_deref())? {
+                    // 从上一帧实际渲染的画面开始渐变
+                    let from = pixels.get(slot.segment.range()).unwrap_or_default().to_vec();
+                    slot.start(Box::new(Fade::new(from, color, transition)), now);
+                }
+            }
+            Command::Brightness(brightness) => {
+                self.strip.set_brightness(brightness);
+                // 亮度变化后即使画面不变也需要重新发送
+                self.last.clear();
+            }
+            Command::SegmentBrightness(segment, brightness) => {
+                for slot in self.slots_mut(Some(&segment))? {
+                    slot.brightness = brightness;
+                }
+            }
+            Command::Segments(segments) => {
+                log::info!("Set segments {:?}", segments);
+                self.slots = segments
+                    .into_iter()
+                    .map(|segment| Slot::new(segment, now))
+                    .collect();
+                // 不属于任何分段的灯珠保持熄灭
+                self.pixels.fill(RGB8::default());
+            }
+        }
+        Ok(())
+    }
+
+    fn render_frame(&mut self, now: Instant) -> Result<()> {
+        for slot in self.slots.iter_mut() {
+            slot.render(&mut self.pixels, now);
+        }
+
+        // 画面没有变化时不重复发送
+        if self.pixels == self.last {
+            return Ok(());
+        }
+        self.strip.write(self.pixels.iter().copied())?;
+        self.last.clone_from(&self.pixels);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::{ effect::Rainbow, sim::SimulatedStrip, Easing, RGBW8 };
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+
+    fn runner(len: usize) -> (EffectRunner<SimulatedStrip>, EffectHandle) {
+        EffectRunner::new(SimulatedStrip::new(len), 30)
+    }
+
+    fn rgb(frame_pixels: &[RGBW8]) -> Vec<RGB8> {
+        frame_pixels.iter().map(|p| p.rgb()).collect()
+    }
+
+    #[test]
+    fn unchanged_frames_are_not_resent() {
+        let (mut runner, handle) = runner(3);
+        let log = runner.strip().log();
+        let t0 = Instant::now();
+        handle.fade_to(RED, Transition::instant()).unwrap();
+        assert!(runner.step(t0));
+        assert!(runner.step(t0 + Duration::from_millis(33)));
+        assert_eq!(log.len(), 1);
+        assert_eq!(rgb(&log.last().unwrap().pixels), [RED; 3]);
+
+        // 亮度变化后即使画面不变也要重新发送
+        handle.set_brightness(0).unwrap();
+        runner.step(t0 + Duration::from_millis(66));
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.last().unwrap().pixels, [RGBW8::default(); 3]);
+    }
+
+    #[test]
+    fn fade_follows_transition() {
+        let (mut runner, handle) = runner(1);
+        let log = runner.strip().log();
+        let t0 = Instant::now();
+        handle.fade_to(RGB8::new(200, 200, 200), Transition::new(1000, Easing::Linear)).unwrap();
+        runner.step(t0);
+        runner.step(t0 + Duration::from_millis(500));
+        runner.step(t0 + Duration::from_millis(1000));
+
+        let frames = log.frames();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].pixels[0], RGBW8::default());
+        let mid = frames[1].pixels[0];
+        assert!(mid.r > 0 && mid.r < 200);
+        assert_eq!(frames[2].pixels[0].rgb(), RGB8::new(200, 200, 200));
+    }
+
+    #[test]
+    fn segments_render_independently() {
+        let (mut runner, handle) = runner(4);
+        let log = runner.strip().log();
+        let t0 = Instant::now();
+        handle
+            .set_segments(vec![Segment::new("left", 0, 2), Segment::new("right", 2, 2)])
+            .unwrap();
+        handle.fade_segment_to("left", RED, Transition::instant()).unwrap();
+        handle.fade_segment_to("right", RED, Transition::instant()).unwrap();
+        handle.set_segment_brightness("right", 128).unwrap();
+        runner.step(t0);
+
+        let half = RGB8::new(128, 0, 0);
+        assert_eq!(rgb(&log.last().unwrap().pixels), [RED, RED, half, half]);
+    }
+
+    #[test]
+    fn unknown_segment_is_ignored() {
+        let (mut runner, handle) = runner(2);
+        handle.fade_segment_to("nope", RED, Transition::instant()).unwrap();
+        runner.step(Instant::now());
+        assert_eq!(runner.pixels(), [RGB8::default(); 2]);
+        assert!(handle.set_segments(vec![Segment::new("big", 0, 3)]).is_err());
+    }
+
+    #[test]
+    fn handle_tracks_scene() {
+        let (mut runner, handle) = runner(2);
+        let effect = EffectConfig::Rainbow(Rainbow::default());
+        handle.set_effect(effect.clone()).unwrap();
+        handle.set_brightness(64).unwrap();
+        assert_eq!(handle.scene(), Scene::new(effect, 64));
+
+        // 纯色场景以默认过渡渐变过去
+        let t0 = Instant::now();
+        handle.apply_scene(&Scene::new(EffectConfig::Solid(Solid { color: RED }), 255)).unwrap();
+        runner.step(t0);
+        assert_eq!(runner.pixels(), [RGB8::default(); 2]);
+        runner.step(t0 + Transition::default().duration());
+        assert_eq!(runner.pixels(), [RED; 2]);
+    }
+
+    #[test]
+    fn stops_when_handles_are_dropped() {
+        let (mut runner, handle) = runner(1);
+        drop(handle);
+        assert!(!runner.step(Instant::now()));
+    }
+}
@@ -0,0 +1,143 @@
+//! WS2812 位编码器
+//!
+//! 把字节流转换成码元和 (电平, 持续时间) 组成的脉冲序列，不依赖任何硬件，
+//! 可以直接在主机上测试：
+//!
+//! ```text
+//! cargo test --lib --target x86_64-unknown-linux-gnu
+//! ```
+use std::time::Duration;
+
+/// 脉冲电平
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Level {
+    High,
+    Low,
+}
+
+/// 一个脉冲：电平和持续时间
+pub type Symbol = (Level, Duration);
+
+/// 灯珠的时序参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// '0'码的高电平时间
+    pub t0h: Duration,
+    /// '0'码的低电平时间
+    pub t0l: Duration,
+    /// '1'码的高电平时间
+    pub t1h: Duration,
+    /// '1'码的低电平时间
+    pub t1l: Duration,
+    /// 帧结束后用于锁存数据的低电平时间
+    pub reset: Duration,
+}
+
+impl Timing {
+    /// WS2812B的时序
+    pub const WS2812B: Timing = Timing {
+        t0h: Duration::from_nanos(400),
+        t0l: Duration::from_nanos(850),
+        t1h: Duration::from_nanos(800),
+        t1l: Duration::from_nanos(450),
+        reset: Duration::from_micros(50),
+    };
+
+    /// 一位数据对应的两个脉冲
+    pub fn bit(&self, bit: bool) -> [Symbol; 2] {
+        if bit {
+            [(Level::High, self.t1h), (Level::Low, self.t1l)]
+        } else {
+            [(Level::High, self.t0h), (Level::Low, self.t0l)]
+        }
+    }
+}
+
+/// 编码后的一个码元：一位数据，或者帧末尾的复位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    Bit(bool),
+    Reset,
+}
+
+/// 把字节流从最高位开始拆成码元，并在末尾追加复位
+///
+/// 驱动把每个码元换成预先算好的RMT脉冲发送，主机上用`encode_bytes`换成`Symbol`测试。
+pub fn codes(bytes: impl IntoIterator<Item = u8>) -> impl Iterator<Item = Code> {
+    bytes
+        .into_iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| Code::Bit((byte >> i) & 1 != 0)))
+        .chain(std::iter::once(Code::Reset))
+}
+
+/// 把原始字节流编码成脉冲序列，每一位两个脉冲，末尾是复位低电平
+pub fn encode_bytes(bytes: &[u8], timing: &Timing) -> Vec<Symbol> {
+    let mut out = Vec::with_capacity(bytes.len() * 16 + 1);
+    for code in codes(bytes.iter().copied()) {
+        match code {
+            Code::Bit(bit) => out.extend_from_slice(&timing.bit(bit)),
+            Code::Reset => out.push((Level::Low, timing.reset)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T: Timing = Timing::WS2812B;
+
+    fn bits(symbols: &[Symbol]) -> Vec<bool> {
+        symbols
+            .chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| {
+                assert_eq!(pair[0].0, Level::High);
+                assert_eq!(pair[1].0, Level::Low);
+                pair[0].1 == T.t1h
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bytes_are_msb_first() {
+        let codes = codes([0b1010_0001, 0xff]).collect::<Vec<_>>();
+        assert_eq!(codes.len(), 2 * 8 + 1);
+        let bits = codes[..8].iter().map(|c| *c == Code::Bit(true)).collect::<Vec<_>>();
+        assert_eq!(bits, [true, false, true, false, false, false, false, true]);
+        assert!(codes[8..16].iter().all(|c| *c == Code::Bit(true)));
+        assert_eq!(codes[16], Code::Reset);
+    }
+
+    #[test]
+    fn symbols_follow_codes() {
+        let symbols = encode_bytes(&[0b1010_0001], &T);
+        assert_eq!(symbols.len(), 16 + 1);
+        assert_eq!(bits(&symbols[..16]), [true, false, true, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn timings_match_bit_value() {
+        assert_eq!(T.bit(false), [(Level::High, T.t0h), (Level::Low, T.t0l)]);
+        assert_eq!(T.bit(true), [(Level::High, T.t1h), (Level::Low, T.t1l)]);
+        assert_eq!(T.t0h, Duration::from_nanos(400));
+        assert_eq!(T.t0l, Duration::from_nanos(850));
+        assert_eq!(T.t1h, Duration::from_nanos(800));
+        assert_eq!(T.t1l, Duration::from_nanos(450));
+    }
+
+    #[test]
+    fn frame_ends_with_reset_gap() {
+        let symbols = encode_bytes(&[1, 2, 3, 4, 5, 6], &T);
+        assert_eq!(symbols.len(), 6 * 16 + 1);
+        assert_eq!(*symbols.last().unwrap(), (Level::Low, T.reset));
+        assert!(T.reset >= Duration::from_micros(50));
+    }
+
+    #[test]
+    fn empty_frame_is_only_reset() {
+        assert_eq!(codes([]).collect::<Vec<_>>(), [Code::Reset]);
+        assert_eq!(encode_bytes(&[], &T), vec![(Level::Low, T.reset)]);
+    }
+}
@@ -0,0 +1,211 @@
+//! 5x7点阵字体
+//!
+//! 内置ASCII可打印字符（0x20-0x7E），每个字符5列，每列一个字节，最低位为最上面一行。
+//! 字符之间留一列空白，所以每个字符占6列。
+use super::{ MatrixView, RGB8 };
+
+/// 字符宽度（列）
+pub const CHAR_WIDTH: i32 = 5;
+/// 字符高度（行）
+pub const CHAR_HEIGHT: i32 = 7;
+/// 包括间隔在内每个字符占用的列数
+pub const CHAR_ADVANCE: i32 = CHAR_WIDTH + 1;
+
+const FIRST: u8 = 0x20;
+const LAST: u8 = 0x7e;
+
+#[rustfmt::skip]
+const GLYPHS: [[u8; 5]; (LAST - FIRST + 1) as usize] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x01, 0x01], // F
+    [0x3e, 0x41, 0x41, 0x51, 0x32], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x04, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x7f, 0x20, 0x18, 0x20, 0x7f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x03, 0x04, 0x78, 0x04, 0x03], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x08, 0x14, 0x54, 0x54, 0x3c], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x00, 0x7f, 0x10, 0x28, 0x44], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+/// 字符的点阵数据，不支持的字符显示为'?'
+pub fn glyph(ch: char) -> &'static [u8; 5] {
+    let code = if (FIRST as u32..=LAST as u32).contains(&(ch as u32)) { ch as u8 } else { b'?' };
+    &GLYPHS[(code - FIRST) as usize]
+}
+
+/// 文字占用的总列数（不含最后一个字符后面的间隔）
+pub fn text_width(text: &str) -> i32 {
+    let count = text.chars().count() as i32;
+    if count == 0 { 0 } else { count * CHAR_ADVANCE - 1 }
+}
+
+/// 在`(x, y)`处绘制一个字符，坐标可以为负数，超出点阵的部分会被裁剪
+pub fn draw_char(view: &mut MatrixView, x: i32, y: i32, ch: char, color: RGB8) {
+    for (col, bits) in glyph(ch).iter().enumerate() {
+        for row in 0..CHAR_HEIGHT {
+            if (bits >> row) & 1 == 0 {
+                continue;
+            }
+            let (px, py) = (x + (col as i32), y + row);
+            if px >= 0 && py >= 0 {
+                view.set(px as usize, py as usize, color);
+            }
+        }
+    }
+}
+
+/// 从`(x, y)`开始绘制一行文字
+pub fn draw_text(view: &mut MatrixView, x: i32, y: i32, text: &str, color: RGB8) {
+    for (i, ch) in text.chars().enumerate() {
+        let cx = x + (i as i32) * CHAR_ADVANCE;
+        // 已经完全超出右边界的字符不用再画
+        if cx >= view.width() as i32 {
+            break;
+        }
+        if cx + CHAR_WIDTH > 0 {
+            draw_char(view, cx, y, ch, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::layout::{ MatrixLayout, Wiring };
+
+    const RED: RGB8 = RGB8::new(255, 0, 0);
+
+    fn lit(view: &MatrixView, x: usize, y: usize) -> bool {
+        view.get(x, y) == Some(RED)
+    }
+
+    #[test]
+    fn looks_up_glyphs() {
+        assert_eq!(glyph(' '), &[0; 5]);
+        assert_eq!(glyph('A'), &[0x7e, 0x11, 0x11, 0x11, 0x7e]);
+        assert_eq!(glyph('~'), &[0x08, 0x04, 0x08, 0x10, 0x08]);
+        // 不支持的字符显示为'?'
+        for ch in ['\n', '\u{7f}', 'é', '中'] {
+            assert_eq!(glyph(ch), glyph('?'), "{:?}", ch);
+        }
+        assert_eq!(text_width(""), 0);
+        assert_eq!(text_width("A"), 5);
+        assert_eq!(text_width("中文"), 11);
+    }
+
+    #[test]
+    fn clips_at_matrix_edges() {
+        let layout = MatrixLayout::new(4, 8).wiring(Wiring::Progressive);
+        let mut pixels = [RGB8::default(); 32];
+        let mut view = MatrixView::new(layout, &mut pixels);
+
+        // 'H'的第0列和第4列是竖线，中间三列只有第3行
+        draw_char(&mut view, -2, 0, 'H', RED);
+        assert!(lit(&view, 0, 3) && !lit(&view, 0, 0));
+        assert!((0..7).all(|y| lit(&view, 2, y)));
+        assert!(!lit(&view, 2, 7) && !lit(&view, 3, 0));
+
+        // 右边和下边超出的部分被丢弃
+        view.fill(RGB8::default());
+        draw_char(&mut view, 3, 5, 'H', RED);
+        assert!((5..8).all(|y| lit(&view, 3, y)));
+        assert!((0..3).all(|x| (0..8).all(|y| !lit(&view, x, y))));
+
+        // 上边超出
+        view.fill(RGB8::default());
+        draw_char(&mut view, 0, -4, 'H', RED);
+        assert!((0..3).all(|y| lit(&view, 0, y)) && !lit(&view, 0, 3));
+
+        // 整段文字都在点阵外面时什么也不画
+        view.fill(RGB8::default());
+        draw_text(&mut view, -12, 0, "AB", RED);
+        draw_text(&mut view, 4, 0, "AB", RED);
+        assert!(pixels.iter().all(|p| *p == RGB8::default()));
+    }
+}
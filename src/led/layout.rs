@@ -0,0 +1,250 @@
+//! 灯带布局
+//!
+//! `Segment`把一条物理灯带切分成多个命名的分段，每个分段可以运行自己的灯效；
+//! `MatrixLayout`把灯带映射成二维点阵，灯效可以按`(x, y)`寻址。
+use std::ops::Range;
+
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+use super::RGB8;
+
+/// 灯带上一段连续的灯珠
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    pub name: String,
+    /// 分段第一颗灯珠在整条灯带上的序号
+    pub start: usize,
+    /// 分段包含的灯珠数量
+    pub len: usize,
+}
+
+impl Segment {
+    pub fn new(name: impl Into<String>, start: usize, len: usize) -> Self {
+        Self { name: name.into(), start, len }
+    }
+
+    /// 覆盖整条灯带的分段
+    pub fn whole(len: usize) -> Self {
+        Self::new("all", 0, len)
+    }
+
+    /// 分段最后一颗灯珠之后的序号，溢出时返回`None`
+    pub fn end(&self) -> Option<usize> {
+        self.start.checked_add(self.len)
+    }
+
+    /// 分段在灯带上的范围，`start + len`溢出时为空
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end().unwrap_or(self.start)
+    }
+
+    /// 取出分段对应的像素，分段内的序号从0开始，超出灯带范围时为空
+    pub fn pixels<'a>(&self, strip: &'a mut [RGB8]) -> &'a mut [RGB8] {
+        strip.get_mut(self.range()).unwrap_or_default()
+    }
+
+    /// 检查分段是否都在灯带范围内、互不重叠且名字不重复
+    pub fn validate(segments: &[Segment], strip_len: usize) -> Result<()> {
+        for (i, s) in segments.iter().enumerate() {
+            let Some(end) = s.end().filter(|end| *end <= strip_len) else {
+                bail!("segment {} (start {}, len {}) exceeds strip length {}", s.name, s.start, s.len, strip_len);
+            };
+            for other in &segments[i + 1..] {
+                if other.name == s.name {
+                    bail!("duplicate segment name {}", s.name);
+                }
+                // 后面的分段溢出时会在检查它自己的时候报错
+                let Some(other_end) = other.end() else {
+                    continue;
+                };
+                if s.start < other_end && other.start < end {
+                    bail!("segment {} overlaps segment {}", s.name, other.name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 点阵的走线方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wiring {
+    /// 每一行都从左到右
+    Progressive,
+    /// 蛇形走线：偶数行从左到右，奇数行从右到左
+    #[default]
+    Serpentine,
+}
+
+/// 点阵相对于物理安装方向的顺时针旋转
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// 把二维坐标映射到灯带序号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatrixLayout {
+    /// 物理点阵的列数（每行灯珠数）
+    pub width: usize,
+    /// 物理点阵的行数
+    pub height: usize,
+    #[serde(default)]
+    pub wiring: Wiring,
+    #[serde(default)]
+    pub rotation: Rotation,
+}
+
+impl MatrixLayout {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, wiring: Wiring::default(), rotation: Rotation::default() }
+    }
+
+    pub fn wiring(mut self, wiring: Wiring) -> Self {
+        self.wiring = wiring;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// 灯珠总数
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 旋转之后逻辑上的宽和高
+    pub fn size(&self) -> (usize, usize) {
+        match self.rotation {
+            Rotation::None | Rotation::Cw180 => (self.width, self.height),
+            Rotation::Cw90 | Rotation::Cw270 => (self.height, self.width),
+        }
+    }
+
+    /// 逻辑坐标`(x, y)`对应的灯带序号，`(0, 0)`为左上角，超出范围返回`None`
+    pub fn index(&self, x: usize, y: usize) -> Option<usize> {
+        let (w, h) = self.size();
+        if x >= w || y >= h {
+            return None;
+        }
+        let (px, py) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Cw90 => (y, self.height - 1 - x),
+            Rotation::Cw180 => (self.width - 1 - x, self.height - 1 - y),
+            Rotation::Cw270 => (self.width - 1 - y, x),
+        };
+        let px = match self.wiring {
+            Wiring::Serpentine if py % 2 == 1 => self.width - 1 - px,
+            _ => px,
+        };
+        Some(py * self.width + px)
+    }
+}
+
+/// 按二维坐标访问像素缓冲
+pub struct MatrixView<'a> {
+    layout: MatrixLayout,
+    pixels: &'a mut [RGB8],
+}
+
+impl<'a> MatrixView<'a> {
+    pub fn new(layout: MatrixLayout, pixels: &'a mut [RGB8]) -> Self {
+        Self { layout, pixels }
+    }
+
+    pub fn width(&self) -> usize {
+        self.layout.size().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.layout.size().1
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<RGB8> {
+        self.layout.index(x, y).and_then(|i| self.pixels.get(i).copied())
+    }
+
+    /// 设置`(x, y)`处的颜色，超出范围时忽略
+    pub fn set(&mut self, x: usize, y: usize, color: RGB8) {
+        if let Some(p) = self.layout.index(x, y).and_then(|i| self.pixels.get_mut(i)) {
+            *p = color;
+        }
+    }
+
+    pub fn fill(&mut self, color: RGB8) {
+        self.pixels.fill(color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_segments() {
+        let ok = [Segment::new("a", 0, 5), Segment::new("b", 5, 5)];
+        assert!(Segment::validate(&ok, 10).is_ok());
+        // 超出灯带
+        assert!(Segment::validate(&[Segment::new("a", 6, 5)], 10).is_err());
+        // 重叠和重名
+        assert!(Segment::validate(&[Segment::new("a", 0, 6), Segment::new("b", 5, 5)], 10).is_err());
+        assert!(Segment::validate(&[Segment::new("a", 0, 2), Segment::new("a", 5, 2)], 10).is_err());
+        // start + len溢出
+        let huge = Segment::new("huge", usize::MAX, 2);
+        assert!(Segment::validate(std::slice::from_ref(&huge), 10).is_err());
+        assert!(Segment::validate(&[Segment::new("a", 0, 2), huge.clone()], 10).is_err());
+        assert_eq!(huge.end(), None);
+        assert!(huge.pixels(&mut [RGB8::default(); 10]).is_empty());
+    }
+
+    #[test]
+    fn maps_serpentine_and_progressive_wiring() {
+        let serpentine = MatrixLayout::new(4, 2);
+        assert_eq!(serpentine.index(0, 0), Some(0));
+        assert_eq!(serpentine.index(3, 0), Some(3));
+        assert_eq!(serpentine.index(0, 1), Some(7));
+        assert_eq!(serpentine.index(3, 1), Some(4));
+        assert_eq!(serpentine.index(4, 0), None);
+
+        let progressive = serpentine.wiring(Wiring::Progressive);
+        assert_eq!(progressive.index(0, 1), Some(4));
+    }
+
+    #[test]
+    fn maps_each_rotation() {
+        // 物理上3列2行的蛇形点阵：
+        // 0 1 2
+        // 5 4 3
+        let layout = MatrixLayout::new(3, 2);
+        let cases = [
+            (Rotation::None, (3, 2), [0, 1, 2, 5, 4, 3]),
+            (Rotation::Cw90, (2, 3), [5, 0, 4, 1, 3, 2]),
+            (Rotation::Cw180, (3, 2), [3, 4, 5, 2, 1, 0]),
+            (Rotation::Cw270, (2, 3), [2, 3, 1, 4, 0, 5]),
+        ];
+        for (rotation, size, expected) in cases {
+            let layout = layout.rotation(rotation);
+            assert_eq!(layout.size(), size);
+            let (w, h) = size;
+            let indices: Vec<_> = (0..h)
+                .flat_map(|y| (0..w).map(move |x| (x, y)))
+                .map(|(x, y)| layout.index(x, y).unwrap())
+                .collect();
+            assert_eq!(indices, expected, "{:?}", rotation);
+            assert_eq!(layout.index(w, 0), None);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+pub use rgb::RGB8;
+
+// 纯软件的位编码器，可以在主机上测试
+pub mod encoder;
+// 灯珠芯片描述（时序、字节顺序、通道数）
+pub mod chipset;
+mod pixel;
+// 亮度、伽马校正和功率限制
+pub mod processing;
+// 灯效引擎和内置灯效
+pub mod effect;
+// HSV/HSL/色温颜色模型和颜色插值
+pub mod color;
+// 带缓动曲线的颜色过渡
+pub mod transition;
+// 分段和二维点阵布局
+pub mod layout;
+// 点阵字体
+pub mod font;
+// 可以保存到NVS的灯光场景
+pub mod scene;
+// 灯带输出的抽象和用于测试的模拟灯带
+pub mod writer;
+pub mod sim;
+// HTTP和BLE前端的请求
+pub mod control;
+pub use chipset::{ Chipset, ColorOrder };
+pub use color::{ Cct, ColorSpec, Hsl, Hsv };
+pub use layout::{ MatrixLayout, MatrixView, Segment };
+pub use pixel::RGBW8;
+pub use processing::{ FrameProcessor, FrameStats, PowerBudget };
+pub use scene::Scene;
+pub use sim::SimulatedStrip;
+pub use writer::SmartLedWriter;
+pub use transition::{ Easing, Transition };
+// 基于RMT外设的驱动，只能在ESP-IDF上编译
+#[cfg(target_os = "espidf")]
+mod ws2812;
+#[cfg(target_os = "espidf")]
+pub use ws2812::WS2812RMT;
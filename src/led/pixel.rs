@@ -0,0 +1,47 @@
+use rgb::RGB8;
+
+/// 带独立白色通道的像素，用于SK6812 RGBW这类四通道灯珠
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RGBW8 {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub w: u8,
+}
+
+impl RGBW8 {
+    pub const fn new(r: u8, g: u8, b: u8, w: u8) -> Self {
+        Self { r, g, b, w }
+    }
+
+    /// 从RGB中提取白色分量：三个通道的公共部分交给白色灯珠发光
+    pub fn from_rgb_auto_white(rgb: RGB8) -> Self {
+        let w = rgb.r.min(rgb.g).min(rgb.b);
+        Self::new(rgb.r - w, rgb.g - w, rgb.b - w, w)
+    }
+
+    /// 如果还没有白色分量，则自动从RGB中提取
+    pub fn with_auto_white(self) -> Self {
+        if self.w == 0 { Self::from_rgb_auto_white(self.rgb()) } else { self }
+    }
+
+    /// 丢弃白色通道，只取RGB部分
+    pub fn rgb(&self) -> RGB8 {
+        RGB8::new(self.r, self.g, self.b)
+    }
+
+    /// 把白色通道叠加回RGB，用于只有三个通道的灯珠
+    pub fn to_rgb(&self) -> RGB8 {
+        RGB8::new(
+            self.r.saturating_add(self.w),
+            self.g.saturating_add(self.w),
+            self.b.saturating_add(self.w)
+        )
+    }
+}
+
+impl From<RGB8> for RGBW8 {
+    fn from(rgb: RGB8) -> Self {
+        Self::new(rgb.r, rgb.g, rgb.b, 0)
+    }
+}
@@ -0,0 +1,226 @@
+//! 帧处理阶段
+//!
+//! 位于帧缓冲和编码器之间：依次做全局亮度、伽马校正和电流限制，
+//! 驱动每次`show`都会经过这里，调用方不需要自己处理。
+use super::RGBW8;
+
+/// 全局亮度、伽马校正和功率限制的配置
+#[derive(Debug, Clone)]
+pub struct FrameProcessor {
+    brightness: u8,
+    gamma: Option<GammaTable>,
+    power: Option<PowerBudget>,
+}
+
+/// 每帧处理后的统计信息
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// 估算的整条灯带电流（毫安）
+    pub estimated_ma: u32,
+    /// 为满足电流预算额外施加的缩放系数，255表示没有缩放
+    pub power_scale: u8,
+}
+
+/// 伽马查找表
+#[derive(Debug, Clone, PartialEq)]
+pub struct GammaTable {
+    pub gamma: f32,
+    table: [u8; 256],
+}
+
+impl GammaTable {
+    pub fn new(gamma: f32) -> Self {
+        let mut table = [0u8; 256];
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = ((i as f32 / 255.0).powf(gamma) * 255.0).round() as u8;
+        }
+        Self { gamma, table }
+    }
+
+    pub fn apply(&self, value: u8) -> u8 {
+        self.table[value as usize]
+    }
+}
+
+/// 电源能提供的电流预算以及灯珠的功耗模型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerBudget {
+    /// 电源最多能提供的电流（毫安）
+    pub max_ma: u32,
+    /// 单个通道满亮度时的电流（毫安），WS2812B约为20mA
+    pub ma_per_channel: f32,
+    /// 每颗灯珠即使熄灭也会消耗的静态电流（毫安）
+    pub idle_ma_per_pixel: f32,
+}
+
+impl PowerBudget {
+    pub fn new(max_ma: u32) -> Self {
+        Self { max_ma, ma_per_channel: 20.0, idle_ma_per_pixel: 1.0 }
+    }
+
+    /// 估算一帧的电流（毫安）
+    pub fn estimate(&self, pixels: &[RGBW8]) -> f32 {
+        let channels: u32 = pixels
+            .iter()
+            .map(|p| (p.r as u32) + (p.g as u32) + (p.b as u32) + (p.w as u32))
+            .sum();
+        self.idle_ma(pixels.len()) + ((channels as f32) / 255.0) * self.ma_per_channel
+    }
+
+    fn idle_ma(&self, len: usize) -> f32 {
+        (len as f32) * self.idle_ma_per_pixel
+    }
+}
+
+impl Default for FrameProcessor {
+    fn default() -> Self {
+        Self { brightness: 255, gamma: None, power: None }
+    }
+}
+
+impl FrameProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// 设置全局亮度（0-255）
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn gamma(&self) -> Option<f32> {
+        self.gamma.as_ref().map(|g| g.gamma)
+    }
+
+    /// 设置伽马值，`None`表示关闭伽马校正，常用值为2.2到2.8
+    pub fn set_gamma(&mut self, gamma: Option<f32>) {
+        self.gamma = gamma.map(GammaTable::new);
+    }
+
+    pub fn power_budget(&self) -> Option<&PowerBudget> {
+        self.power.as_ref()
+    }
+
+    /// 设置电流预算，`None`表示不限制
+    pub fn set_power_budget(&mut self, power: Option<PowerBudget>) {
+        self.power = power;
+    }
+
+    /// 处理一帧：`input`是帧缓冲，结果写入`output`
+    pub fn process(&self, input: &[RGBW8], output: &mut Vec<RGBW8>) -> FrameStats {
+        output.clear();
+        output.extend(input.iter().map(|p| self.correct(*p)));
+
+        let Some(power) = self.power else {
+            return FrameStats {
+                estimated_ma: PowerBudget::new(0).estimate(output).round() as u32,
+                power_scale: 255,
+            };
+        };
+
+        let mut estimated = power.estimate(output);
+        let mut power_scale = 255u8;
+        if estimated > (power.max_ma as f32) {
+            // 静态电流无法通过调暗消除，只缩放可变部分
+            let idle = power.idle_ma(output.len());
+            let scale = (((power.max_ma as f32) - idle) / (estimated - idle)).clamp(0.0, 1.0);
+            power_scale = (scale * 255.0) as u8;
+            let corrected = output.clone();
+            loop {
+                output.clear();
+                output.extend(corrected.iter().map(|p| scale_pixel(*p, power_scale)));
+                estimated = power.estimate(output);
+                // 通道取整可能让结果略高于预算，继续降低直到满足为止
+                if estimated <= (power.max_ma as f32) || power_scale == 0 {
+                    break;
+                }
+                power_scale -= 1;
+            }
+        }
+        FrameStats { estimated_ma: estimated.round() as u32, power_scale }
+    }
+
+    fn correct(&self, p: RGBW8) -> RGBW8 {
+        let p = scale_pixel(p, self.brightness);
+        match &self.gamma {
+            Some(g) => RGBW8::new(g.apply(p.r), g.apply(p.g), g.apply(p.b), g.apply(p.w)),
+            None => p,
+        }
+    }
+}
+
+/// 按比例缩放一个通道，255表示保持不变
+pub fn scale(value: u8, factor: u8) -> u8 {
+    (((value as u16) * (factor as u16) + 127) / 255) as u8
+}
+
+/// 按比例缩放像素的所有通道
+pub fn scale_pixel(p: RGBW8, factor: u8) -> RGBW8 {
+    RGBW8::new(scale(p.r, factor), scale(p.g, factor), scale(p.b, factor), scale(p.w, factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: RGBW8 = RGBW8::new(255, 255, 255, 0);
+
+    fn process(processor: &FrameProcessor, input: &[RGBW8]) -> (FrameStats, Vec<RGBW8>) {
+        let mut output = Vec::new();
+        let stats = processor.process(input, &mut output);
+        (stats, output)
+    }
+
+    #[test]
+    fn brightness_and_gamma() {
+        let mut processor = FrameProcessor::new();
+        processor.set_brightness(128);
+        let (stats, output) = process(&processor, &[WHITE]);
+        assert_eq!(output, [RGBW8::new(128, 128, 128, 0)]);
+        assert_eq!(stats.power_scale, 255);
+
+        let gamma = GammaTable::new(2.8);
+        assert_eq!((gamma.apply(0), gamma.apply(255)), (0, 255));
+        assert!(gamma.apply(128) < 128);
+        processor.set_brightness(255);
+        processor.set_gamma(Some(2.2));
+        let (_, output) = process(&processor, &[RGBW8::new(0, 255, 0, 0)]);
+        assert_eq!(output, [RGBW8::new(0, 255, 0, 0)]);
+    }
+
+    #[test]
+    fn power_budget_limits_current() {
+        let budget = PowerBudget::new(1000);
+        let mut processor = FrameProcessor::new();
+        processor.set_power_budget(Some(budget));
+        let input = vec![WHITE; 60];
+        // 全亮时约 60 * (1 + 60) = 3660mA
+        assert!(budget.estimate(&input) > 3000.0);
+
+        let (stats, output) = process(&processor, &input);
+        assert!(stats.estimated_ma <= budget.max_ma);
+        assert!(stats.power_scale < 255);
+        assert!(budget.estimate(&output) <= (budget.max_ma as f32));
+        // 静态电流计入估算：熄灭的灯带也有60mA
+        let (stats, _) = process(&processor, &[RGBW8::default(); 60]);
+        assert_eq!((stats.estimated_ma, stats.power_scale), (60, 255));
+    }
+
+    #[test]
+    fn budget_below_idle_current_turns_everything_off() {
+        let mut processor = FrameProcessor::new();
+        processor.set_power_budget(Some(PowerBudget::new(10)));
+        let (stats, output) = process(&processor, &[WHITE; 60]);
+        assert_eq!(stats.power_scale, 0);
+        assert!(output.iter().all(|p| *p == RGBW8::default()));
+        assert_eq!(stats.estimated_ma, 60);
+
+        // 熄灭的帧同样超出预算，不能出现除零
+        let (stats, _) = process(&processor, &[RGBW8::default(); 60]);
+        assert_eq!(stats.power_scale, 0);
+    }
+}
@@ -0,0 +1,162 @@
+//! 场景
+//!
+//! 场景是一组可以整体恢复的灯光状态（灯效及其参数、全局亮度），
+//! 可以按名字保存到NVS中，重启后恢复上一次激活的场景。
+//!
+//! 只有通过召回场景激活的状态会在重启后恢复：`/set-color`、`/effect`和定时动作
+//! 修改的灯光不会写入NVS，避免频繁调色时反复擦写Flash。需要重启后保持的状态
+//! 应先保存为场景再召回。
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+use super::effect::EffectConfig;
+
+/// NVS键名的最大长度
+pub const MAX_NAME_LEN: usize = 15;
+
+/// 灯光场景，纯色也是一种灯效（`solid`）
+///
+/// ```json
+/// { "brightness": 128, "effect": "rainbow", "cycle_ms": 5000 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    #[serde(default = "full_brightness")]
+    pub brightness: u8,
+    #[serde(flatten)]
+    pub effect: EffectConfig,
+}
+
+fn full_brightness() -> u8 {
+    255
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self { brightness: full_brightness(), effect: EffectConfig::default() }
+    }
+}
+
+impl Scene {
+    pub fn new(effect: EffectConfig, brightness: u8) -> Self {
+        Self { brightness, effect }
+    }
+}
+
+/// 检查场景名是否能作为NVS的键：1-15个字节，且不能以`_`开头（保留给内部使用）
+pub fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        bail!("scene name must be 1-{} bytes, got {:?}", MAX_NAME_LEN, name);
+    }
+    if name.starts_with('_') {
+        bail!("scene name {:?} must not start with '_'", name);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "espidf")]
+pub use store::SceneStore;
+
+#[cfg(target_os = "espidf")]
+mod store {
+    use anyhow::Result;
+    use esp_idf_svc::nvs::{ EspDefaultNvsPartition, EspNvs, NvsDefault };
+
+    use super::{ check_name, Scene };
+    use crate::storage::{ read_json, write_json };
+
+    const NAMESPACE: &str = "led_scenes";
+    // 所有场景名的列表，NVS本身不能列出命名空间下的键
+    const INDEX_KEY: &str = "_index";
+    // 上一次激活的场景，单独保存一份，删除同名场景后依然可以恢复
+    const LAST_KEY: &str = "_last";
+
+    /// 保存在NVS中的场景，每个场景以JSON的形式存为一个blob
+    pub struct SceneStore {
+        nvs: EspNvs<NvsDefault>,
+    }
+
+    impl SceneStore {
+        pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+            Ok(Self { nvs: EspNvs::new(partition, NAMESPACE, true)? })
+        }
+
+        /// 所有已保存的场景名，按保存的先后顺序
+        pub fn list(&self) -> Result<Vec<String>> {
+            Ok(read_json(&self.nvs, INDEX_KEY)?.unwrap_or_default())
+        }
+
+        pub fn load(&self, name: &str) -> Result<Option<Scene>> {
+            check_name(name)?;
+            read_json(&self.nvs, name)
+        }
+
+        /// 保存场景，同名场景会被覆盖
+        pub fn save(&mut self, name: &str, scene: &Scene) -> Result<()> {
+            check_name(name)?;
+            write_json(&mut self.nvs, name, scene)?;
+            let mut names = self.list()?;
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+                write_json(&mut self.nvs, INDEX_KEY, &names)?;
+            }
+            Ok(())
+        }
+
+        /// 删除场景，返回场景是否存在
+        pub fn delete(&mut self, name: &str) -> Result<bool> {
+            check_name(name)?;
+            let removed = self.nvs.remove(name)?;
+            let mut names = self.list()?;
+            names.retain(|n| n != name);
+            write_json(&mut self.nvs, INDEX_KEY, &names)?;
+            Ok(removed)
+        }
+
+        /// 上一次召回的场景，启动时用来恢复灯光
+        pub fn last(&self) -> Result<Option<Scene>> {
+            read_json(&self.nvs, LAST_KEY)
+        }
+
+        /// 记录召回的场景，只在召回场景时调用
+        pub fn set_last(&mut self, scene: &Scene) -> Result<()> {
+            write_json(&mut self.nvs, LAST_KEY, scene)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_names() {
+        assert!(check_name("a").is_ok());
+        assert!(check_name("living_room").is_ok());
+        assert!(check_name(&"x".repeat(MAX_NAME_LEN)).is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+        // 长度按字节算：5个汉字是15字节，6个超出
+        assert!(check_name("客厅晚上灯").is_ok());
+        assert!(check_name("客厅晚上的灯").is_err());
+        // 以`_`开头的键保留给索引
+        assert!(check_name("_index").is_err());
+        assert!(check_name("a_b").is_ok());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        use crate::led::effect::Rainbow;
+
+        let scene = Scene::new(EffectConfig::Rainbow(Rainbow { cycle_ms: 2000 }), 64);
+        let json = serde_json::to_value(&scene).unwrap();
+        // 灯效参数和亮度展开在同一层
+        assert_eq!(json, serde_json::json!({ "brightness": 64, "effect": "rainbow", "cycle_ms": 2000 }));
+        assert_eq!(serde_json::from_value::<Scene>(json).unwrap(), scene);
+
+        // 不写亮度时默认为最亮
+        let scene: Scene = serde_json::from_str(r#"{ "effect": "rainbow", "cycle_ms": 2000 }"#).unwrap();
+        assert_eq!(scene.brightness, 255);
+        assert_eq!(scene.effect, EffectConfig::Rainbow(Rainbow { cycle_ms: 2000 }));
+    }
+}
@@ -0,0 +1,154 @@
+//! 模拟灯带
+//!
+//! 不依赖任何外设，把每次写入的帧连同时间戳记录下来，
+//! 测试可以断言真实硬件上会发送出去的每一帧。
+use std::{ sync::{ Arc, Mutex }, time::{ Duration, Instant } };
+
+use anyhow::Result;
+
+use super::{ writer::SmartLedWriter, Chipset, FrameProcessor, FrameStats, RGBW8 };
+
+/// 一帧发送记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// 相对于灯带创建时的时间
+    pub at: Duration,
+    /// 经过亮度、伽马和功率限制处理后的像素
+    pub pixels: Vec<RGBW8>,
+    /// 按芯片的字节顺序排列的字节流，即实际发送的数据
+    pub bytes: Vec<u8>,
+    pub stats: FrameStats,
+}
+
+/// 记录下来的帧，可以克隆后在灯带被移动到灯效线程之后继续读取
+#[derive(Debug, Clone, Default)]
+pub struct FrameLog {
+    frames: Arc<Mutex<Vec<Frame>>>,
+}
+
+impl FrameLog {
+    /// 所有记录的帧，按发送顺序
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    pub fn last(&self) -> Option<Frame> {
+        self.frames.lock().unwrap().last().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.frames.lock().unwrap().clear();
+    }
+
+    fn push(&self, frame: Frame) {
+        self.frames.lock().unwrap().push(frame);
+    }
+}
+
+/// 内存中的模拟灯带，帧处理和字节顺序与`WS2812RMT`一致
+pub struct SimulatedStrip {
+    chipset: Chipset,
+    processor: FrameProcessor,
+    pixels: Vec<RGBW8>,
+    started: Instant,
+    log: FrameLog,
+}
+
+impl SimulatedStrip {
+    /// 创建包含`len`颗WS2812B灯珠的模拟灯带
+    pub fn new(len: usize) -> Self {
+        Self::with_chipset(len, Chipset::WS2812B)
+    }
+
+    pub fn with_chipset(len: usize, chipset: Chipset) -> Self {
+        Self {
+            chipset,
+            processor: FrameProcessor::default(),
+            pixels: vec![RGBW8::default(); len],
+            started: Instant::now(),
+            log: FrameLog::default(),
+        }
+    }
+
+    pub fn chipset(&self) -> &Chipset {
+        &self.chipset
+    }
+
+    pub fn processor(&self) -> &FrameProcessor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut FrameProcessor {
+        &mut self.processor
+    }
+
+    /// 发送记录的句柄
+    pub fn log(&self) -> FrameLog {
+        self.log.clone()
+    }
+}
+
+impl SmartLedWriter for SimulatedStrip {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.processor.set_brightness(brightness);
+    }
+
+    fn write<I>(&mut self, pixels: I) -> Result<FrameStats>
+        where I: IntoIterator, I::Item: Into<RGBW8>
+    {
+        for (dst, src) in self.pixels.iter_mut().zip(pixels) {
+            *dst = src.into();
+        }
+        let mut output = Vec::with_capacity(self.pixels.len());
+        let stats = self.processor.process(&self.pixels, &mut output);
+        self.log.push(Frame {
+            at: self.started.elapsed(),
+            bytes: self.chipset.pixel_bytes(&output),
+            pixels: output,
+            stats,
+        });
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::led::{ ColorOrder, RGB8 };
+
+    #[test]
+    fn records_processed_frames() {
+        let mut strip = SimulatedStrip::new(2);
+        let log = strip.log();
+        strip.write([RGB8::new(255, 0, 0), RGB8::new(0, 0, 255)]).unwrap();
+        strip.set_brightness(128);
+        strip.write([RGB8::new(255, 0, 0)]).unwrap();
+
+        let frames = log.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].bytes, [0, 255, 0, 0, 0, 255]);
+        // 只写了第一颗，第二颗保持上一帧的颜色，两颗都按亮度缩放
+        assert_eq!(frames[1].pixels, [RGBW8::new(128, 0, 0, 0), RGBW8::new(0, 0, 128, 0)]);
+        assert!(frames[1].at >= frames[0].at);
+    }
+
+    #[test]
+    fn bytes_follow_chipset_order() {
+        let chipset = Chipset::WS2812B.with_order(ColorOrder::RGB);
+        let mut strip = SimulatedStrip::with_chipset(1, chipset);
+        strip.write([RGB8::new(1, 2, 3)]).unwrap();
+        assert_eq!(strip.log().last().unwrap().bytes, [1, 2, 3]);
+    }
+}
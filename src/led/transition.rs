@@ -0,0 +1,160 @@
+//! 颜色过渡
+//!
+//! 颜色变化不再瞬间跳变，而是按照缓动曲线在给定的时间内渐变到目标颜色。
+use std::time::Duration;
+
+use serde::{ Deserialize, Serialize };
+
+use super::{ color::lerp_oklab, effect::{ Effect, Tick }, RGB8 };
+
+/// 缓动曲线
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    /// 正弦缓入缓出
+    #[default]
+    EaseInOut,
+    /// 三次方缓入缓出，两端更平缓、中间更快
+    Cubic,
+}
+
+impl Easing {
+    /// 把0-1的线性进度映射为缓动后的进度
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => (1.0 - (std::f32::consts::PI * t).cos()) / 2.0,
+            Easing::Cubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// 过渡参数
+///
+/// ```json
+/// { "duration_ms": 500, "easing": "ease_in_out" }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transition {
+    pub duration_ms: u32,
+    pub easing: Easing,
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self { duration_ms: 500, easing: Easing::default() }
+    }
+}
+
+impl Transition {
+    pub fn new(duration_ms: u32, easing: Easing) -> Self {
+        Self { duration_ms, easing }
+    }
+
+    /// 不做过渡，立即切换
+    pub fn instant() -> Self {
+        Self::new(0, Easing::Linear)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms as u64)
+    }
+
+    /// 经过`elapsed`后缓动后的进度（0-1）
+    pub fn progress(&self, elapsed: Duration) -> f32 {
+        if self.duration_ms == 0 {
+            return 1.0;
+        }
+        self.easing.apply(elapsed.as_secs_f32() / self.duration().as_secs_f32())
+    }
+}
+
+/// 从当前显示的画面渐变到目标颜色的灯效
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fade {
+    from: Vec<RGB8>,
+    to: RGB8,
+    transition: Transition,
+}
+
+impl Fade {
+    /// `from`为开始渐变时灯带上正在显示的画面
+    pub fn new(from: Vec<RGB8>, to: RGB8, transition: Transition) -> Self {
+        Self { from, to, transition }
+    }
+
+    pub fn target(&self) -> RGB8 {
+        self.to
+    }
+}
+
+impl Effect for Fade {
+    fn render(&mut self, tick: &Tick, pixels: &mut [RGB8]) {
+        let t = self.transition.progress(tick.elapsed);
+        for (i, p) in pixels.iter_mut().enumerate() {
+            let from = self.from.get(i).copied().unwrap_or_default();
+            *p = lerp_oklab(from, self.to, t);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 3] = [Easing::Linear, Easing::EaseInOut, Easing::Cubic];
+
+    #[test]
+    fn easing_endpoints() {
+        for easing in EASINGS {
+            assert!(easing.apply(0.0).abs() < 1e-6, "{:?}", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", easing);
+            assert!((easing.apply(0.5) - 0.5).abs() < 1e-6, "{:?}", easing);
+            // 超出范围的进度被截断
+            assert_eq!(easing.apply(-1.0), easing.apply(0.0));
+            assert_eq!(easing.apply(2.0), easing.apply(1.0));
+        }
+    }
+
+    #[test]
+    fn easing_is_monotonic() {
+        for easing in EASINGS {
+            let values = (0..=100).map(|i| easing.apply((i as f32) / 100.0)).collect::<Vec<_>>();
+            assert!(values.windows(2).all(|w| w[0] <= w[1]), "{:?}", easing);
+        }
+        // 缓入缓出在开始阶段比线性慢
+        assert!(Easing::EaseInOut.apply(0.1) < 0.1);
+        assert!(Easing::Cubic.apply(0.1) < Easing::EaseInOut.apply(0.1));
+    }
+
+    #[test]
+    fn zero_duration_is_immediate() {
+        let instant = Transition::instant();
+        assert_eq!(instant.progress(Duration::ZERO), 1.0);
+        assert_eq!(Transition::new(0, Easing::Cubic).progress(Duration::ZERO), 1.0);
+
+        let red = RGB8::new(255, 0, 0);
+        let mut fade = Fade::new(vec![RGB8::default(); 2], red, instant);
+        let mut pixels = [RGB8::default(); 2];
+        fade.render(&(Tick { frame: 0, elapsed: Duration::ZERO }), &mut pixels);
+        assert_eq!(pixels, [red; 2]);
+    }
+
+    #[test]
+    fn progress_follows_duration() {
+        let linear = Transition::new(1000, Easing::Linear);
+        assert_eq!(linear.progress(Duration::ZERO), 0.0);
+        assert!((linear.progress(Duration::from_millis(250)) - 0.25).abs() < 1e-6);
+        assert_eq!(linear.progress(Duration::from_millis(1000)), 1.0);
+        assert_eq!(linear.progress(Duration::from_secs(5)), 1.0);
+    }
+}
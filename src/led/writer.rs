@@ -0,0 +1,24 @@
+//! 灯带输出的抽象
+//!
+//! 灯效线程只通过`SmartLedWriter`写灯带，真实硬件使用`WS2812RMT`，
+//! 在主机上测试时使用`SimulatedStrip`。
+use anyhow::Result;
+
+use super::{ FrameStats, RGBW8 };
+
+/// 可以写入一整帧像素的灯带
+pub trait SmartLedWriter: Send {
+    /// 灯珠数量
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 设置全局亮度（0-255），下一次写入时生效
+    fn set_brightness(&mut self, brightness: u8);
+
+    /// 写入一帧并发送，像素数多于灯珠数时多余的部分被忽略
+    fn write<I>(&mut self, pixels: I) -> Result<FrameStats>
+        where I: IntoIterator, I::Item: Into<RGBW8>;
+}
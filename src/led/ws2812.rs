@@ -0,0 +1,330 @@
+use std::sync::{ Arc, Condvar, Mutex };
+use std::thread::JoinHandle;
+
+use anyhow::{ bail, Result };
+use esp_idf_svc::hal::{
+    gpio::OutputPin,
+    peripheral::Peripheral,
+    rmt::{ config::TransmitConfig, PinState, Pulse, RmtChannel, TxRmtDriver, VariableLengthSignal },
+    units::Hertz,
+};
+
+use super::{ encoder::{ self, Code, Timing }, writer::SmartLedWriter, Chipset, FrameProcessor, FrameStats, RGBW8 };
+
+/// WS2812 灯带驱动
+///
+/// 内部维护一个长度可配置的帧缓冲，`set`/`fill`/`clear` 只修改缓冲。
+/// 调用 `show` 时把处理后的帧拷贝到后台缓冲后立即返回，
+/// 由后台发送线程按照`Chipset`把整帧编码成一个变长的 RMT 信号发送出去，
+/// 0码、1码和复位的脉冲在创建时按芯片时序算好，每帧只按位拼接。
+/// 调用方可以在上一帧发送的同时渲染下一帧。
+pub struct WS2812RMT {
+    chipset: Chipset,
+    processor: FrameProcessor,
+    pixels: Vec<RGBW8>,
+    // 经过亮度、伽马和功率限制处理后真正发送出去的帧
+    output: Vec<RGBW8>,
+    shared: Arc<Shared>,
+    sender: Option<JoinHandle<()>>,
+}
+
+/// 调用方和发送线程之间共享的后台缓冲
+struct Shared {
+    back: Mutex<BackBuffer>,
+    condvar: Condvar,
+    #[cfg(feature = "embassy")]
+    done: esp_idf_svc::hal::task::asynch::Notification,
+}
+
+#[derive(Default)]
+struct BackBuffer {
+    frame: Vec<RGBW8>,
+    // 后台缓冲里有一帧还没被发送线程取走
+    pending: bool,
+    // 发送线程正在发送
+    busy: bool,
+    stop: bool,
+}
+
+impl BackBuffer {
+    fn is_done(&self) -> bool {
+        !self.pending && !self.busy
+    }
+}
+
+impl WS2812RMT {
+    /// 创建只有一颗灯珠的灯带，兼容原来的单像素用法
+    pub fn new(
+        led: impl Peripheral<P = impl OutputPin> + 'static,
+        channel: impl Peripheral<P = impl RmtChannel> + 'static
+    ) -> Result<Self> {
+        Self::with_len(led, channel, 1)
+    }
+
+    /// 创建包含`len`颗WS2812B灯珠的灯带
+    pub fn with_len(
+        led: impl Peripheral<P = impl OutputPin> + 'static,
+        channel: impl Peripheral<P = impl RmtChannel> + 'static,
+        len: usize
+    ) -> Result<Self> {
+        Self::with_chipset(led, channel, len, Chipset::WS2812B)
+    }
+
+    /// 创建包含`len`颗指定芯片灯珠的灯带
+    pub fn with_chipset(
+        led: impl Peripheral<P = impl OutputPin> + 'static,
+        channel: impl Peripheral<P = impl RmtChannel> + 'static,
+        len: usize,
+        chipset: Chipset
+    ) -> Result<Self> {
+        // 配置RMT的传输参数
+        let config = TransmitConfig::new().clock_divider(2);
+        // 初始化RMT驱动
+        let tx = TxRmtDriver::new(channel, led, &config)?;
+        let pulses = Pulses::new(tx.counter_clock()?, &chipset.timing)?;
+
+        let shared = Arc::new(Shared {
+            back: Mutex::new(BackBuffer {
+                frame: Vec::with_capacity(len),
+                ..Default::default()
+            }),
+            condvar: Condvar::new(),
+            #[cfg(feature = "embassy")]
+            done: esp_idf_svc::hal::task::asynch::Notification::new(),
+        });
+        // 启动后台发送线程
+        let sender = {
+            let shared = shared.clone();
+            std::thread::Builder
+                ::new()
+                .name("ws2812-tx".into())
+                .stack_size(4096)
+                .spawn(move || send_loop(tx, chipset, pulses, shared))?
+        };
+
+        Ok(Self {
+            chipset,
+            processor: FrameProcessor::default(),
+            pixels: vec![RGBW8::default(); len],
+            output: Vec::with_capacity(len),
+            shared,
+            sender: Some(sender),
+        })
+    }
+
+    /// 当前使用的灯珠芯片
+    pub fn chipset(&self) -> &Chipset {
+        &self.chipset
+    }
+
+    /// 帧处理配置（亮度、伽马、电流预算）
+    pub fn processor(&self) -> &FrameProcessor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut FrameProcessor {
+        &mut self.processor
+    }
+
+    /// 设置全局亮度（0-255）
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.processor.set_brightness(brightness);
+    }
+
+    /// 灯珠数量
+    pub fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    /// 当前帧缓冲
+    pub fn pixels(&self) -> &[RGBW8] {
+        &self.pixels
+    }
+
+    /// 可写的帧缓冲，修改后需要调用`show`才会生效
+    pub fn pixels_mut(&mut self) -> &mut [RGBW8] {
+        &mut self.pixels
+    }
+
+    /// 设置第`index`颗灯珠的颜色（只修改帧缓冲）
+    pub fn set(&mut self, index: usize, color: impl Into<RGBW8>) -> Result<()> {
+        let len = self.pixels.len();
+        let Some(pixel) = self.pixels.get_mut(index) else {
+            bail!("pixel index {index} out of range (len {len})")
+        };
+        *pixel = color.into();
+        Ok(())
+    }
+
+    /// 把所有灯珠设置为同一个颜色（只修改帧缓冲）
+    pub fn fill(&mut self, color: impl Into<RGBW8>) {
+        self.pixels.fill(color.into());
+    }
+
+    /// 清空帧缓冲（全部置黑）
+    pub fn clear(&mut self) {
+        self.fill(RGBW8::default());
+    }
+
+    /// 把整个帧缓冲经过帧处理后交给后台发送，立即返回这一帧的估算电流
+    ///
+    /// 如果上一帧还没有被发送线程取走，会等待它被取走后再拷贝。
+    pub fn show(&mut self) -> Result<FrameStats> {
+        // 亮度、伽马校正和功率限制
+        let stats = self.processor.process(&self.pixels, &mut self.output);
+
+        let mut back = self.shared.back.lock().unwrap();
+        while back.pending {
+            back = self.shared.condvar.wait(back).unwrap();
+        }
+        // 交换前后台缓冲，避免每帧重新分配
+        std::mem::swap(&mut back.frame, &mut self.output);
+        back.pending = true;
+        self.shared.condvar.notify_all();
+        Ok(stats)
+    }
+
+    /// 阻塞等待已经提交的帧全部发送完成
+    pub fn wait_done(&self) {
+        let mut back = self.shared.back.lock().unwrap();
+        while !back.is_done() {
+            back = self.shared.condvar.wait(back).unwrap();
+        }
+    }
+
+    /// 异步等待已经提交的帧全部发送完成
+    #[cfg(feature = "embassy")]
+    pub async fn done(&self) {
+        loop {
+            if self.shared.back.lock().unwrap().is_done() {
+                return;
+            }
+            self.shared.done.wait().await;
+        }
+    }
+
+    /// 把所有灯珠设置为同一颜色并立即显示
+    pub fn set_pixel(&mut self, rgb: impl Into<RGBW8>) -> Result<FrameStats> {
+        self.fill(rgb);
+        self.show()
+    }
+
+    /// 熄灭所有灯珠，并等待熄灭的帧发送完成
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.clear();
+        self.show()?;
+        self.wait_done();
+        Ok(())
+    }
+}
+
+impl SmartLedWriter for WS2812RMT {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.processor.set_brightness(brightness);
+    }
+
+    /// 写入帧缓冲后调用`show`
+    fn write<I>(&mut self, pixels: I) -> Result<FrameStats>
+        where I: IntoIterator, I::Item: Into<RGBW8>
+    {
+        for (dst, src) in self.pixels.iter_mut().zip(pixels) {
+            *dst = src.into();
+        }
+        self.show()
+    }
+}
+
+impl Drop for WS2812RMT {
+    fn drop(&mut self) {
+        self.shared.back.lock().unwrap().stop = true;
+        self.shared.condvar.notify_all();
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.join();
+        }
+    }
+}
+
+/// 后台发送线程：取走后台缓冲中的帧，编码后阻塞发送
+fn send_loop(mut tx: TxRmtDriver<'static>, chipset: Chipset, pulses: Pulses, shared: Arc<Shared>) {
+    let mut frame = Vec::new();
+    loop {
+        {
+            let mut back = shared.back.lock().unwrap();
+            while !back.pending && !back.stop {
+                back = shared.condvar.wait(back).unwrap();
+            }
+            // 停止前先把最后提交的帧发送出去
+            if !back.pending {
+                return;
+            }
+            std::mem::swap(&mut back.frame, &mut frame);
+            back.pending = false;
+            back.busy = true;
+            // 后台缓冲已经空出来，可以接收下一帧
+            shared.condvar.notify_all();
+        }
+
+        if let Err(e) = transmit(&mut tx, &chipset, &pulses, &frame) {
+            log::error!("Failed to send ws2812 frame: {}", e);
+        }
+
+        shared.back.lock().unwrap().busy = false;
+        shared.condvar.notify_all();
+        #[cfg(feature = "embassy")]
+        shared.done.notify_lsb();
+    }
+}
+
+/// 按芯片时序预先算好的RMT脉冲
+struct Pulses {
+    /// 0码和1码各自的高低电平
+    bits: [[Pulse; 2]; 2],
+    /// 帧结束后的复位低电平
+    reset: Pulse,
+}
+
+impl Pulses {
+    fn new(ticks_hz: Hertz, timing: &Timing) -> Result<Self> {
+        let pulse = |state, duration| Pulse::new_with_duration(ticks_hz, state, &duration);
+        Ok(Self {
+            bits: [
+                [pulse(PinState::High, timing.t0h)?, pulse(PinState::Low, timing.t0l)?],
+                [pulse(PinState::High, timing.t1h)?, pulse(PinState::Low, timing.t1l)?],
+            ],
+            reset: pulse(PinState::Low, timing.reset)?,
+        })
+    }
+
+    /// 码元对应的脉冲
+    fn get(&self, code: Code) -> &[Pulse] {
+        match code {
+            Code::Bit(bit) => &self.bits[bit as usize],
+            Code::Reset => std::slice::from_ref(&self.reset),
+        }
+    }
+}
+
+/// 把一帧编码成一个RMT信号并阻塞发送
+fn transmit(
+    tx: &mut TxRmtDriver<'static>,
+    chipset: &Chipset,
+    pulses: &Pulses,
+    frame: &[RGBW8]
+) -> Result<()> {
+    // 每一位两个脉冲，再加上末尾的复位低电平
+    let len = frame.len() * chipset.bytes_per_pixel() * 16 + 1;
+    let mut signal = VariableLengthSignal::with_capacity(len);
+    for code in encoder::codes(chipset.bytes(frame)) {
+        signal.push(pulses.get(code))?;
+    }
+    tx.start_blocking(&signal)?;
+    Ok(())
+}
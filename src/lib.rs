@@ -1,12 +1,29 @@
+#[cfg(target_os = "espidf")]
 use anyhow::Result;
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::eventloop::EspSystemEventLoop;
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::hal::peripherals::Peripherals;
+#[cfg(target_os = "espidf")]
 use esp_idf_svc::nvs::EspDefaultNvsPartition;
 
 // 导入与WiFi相关的模块，用于后续的WiFi配置和服务。
 pub mod wifi;
 pub mod led;
-// pub mod ble;
+// SNTP时间同步和本地时间
+pub mod clock;
+// 按本地时间触发灯光动作的定时任务
+pub mod schedule;
+// NVS中以JSON保存的配置
+#[cfg(target_os = "espidf")]
+pub mod storage;
+// 基于Bluedroid的BLE框架，需要在sdkconfig中开启Bluedroid
+pub mod ble;
+
+#[cfg(all(target_os = "espidf", feature = "bluedroid", not(esp_idf_bt_bluedroid_enabled)))]
+compile_error!(
+    "feature `bluedroid` needs ESP_IDF_SDKCONFIG_DEFAULTS=\"sdkconfig.defaults;sdkconfig.bluedroid.defaults\""
+);
 
 /**
  * 系统初始化函数。
@@ -15,6 +32,7 @@ pub mod led;
  *
  * @return Result<(EspSystemEventLoop, Peripherals, EspDefaultNvsPartition)> 初始化完成后的系统事件循环、外设句柄和默认NVS分区。
  */
+#[cfg(target_os = "espidf")]
 pub fn init() -> Result<(EspSystemEventLoop, Peripherals, EspDefaultNvsPartition)> {
     // 链接SDK中的补丁，以修正某些功能的兼容性问题。
     esp_idf_svc::sys::link_patches();
@@ -0,0 +1,478 @@
+//! 定时任务
+//!
+//! 按本地时间在固定时刻或日出日落前后触发灯光动作，例如：
+//! - 日落时切换到40%亮度的暖白
+//! - 工作日07:00用10分钟渐亮
+//! - 每天23:00关灯
+//!
+//! 规则保存在NVS中，由`Scheduler`线程每秒检查一次，时间同步之前不会触发任何规则。
+use std::{ sync::{ Arc, Mutex }, time::Duration };
+
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+use crate::{
+    clock::{ Clock, LocalTime },
+    led::{ effect::EffectHandle, ColorSpec, Scene, Transition, RGB8 },
+};
+
+pub mod sun;
+pub use sun::{ Location, SunTimes };
+
+/// 最多可以保存的规则数
+pub const MAX_RULES: usize = 16;
+/// 规则名的最大长度
+pub const MAX_NAME_LEN: usize = 32;
+/// 两次检查之间的间隔超过这个时间时认为时钟发生了跳变（例如第一次同步），跳过中间的规则
+const MAX_CATCH_UP_MINUTES: i64 = 10;
+
+/// 星期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    Sun,
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+}
+
+impl Weekday {
+    /// 工作日，周一到周五
+    pub const WORKDAYS: [Weekday; 5] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ];
+
+    /// 与`LocalTime::weekday`一致，0表示星期日
+    pub fn number(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// 规则的触发时刻，`offset_min`为相对日出日落的偏移（分钟），负数表示提前
+///
+/// ```json
+/// { "type": "time", "hour": 7, "minute": 0 }
+/// { "type": "sunset", "offset_min": -15 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Trigger {
+    Time {
+        hour: u8,
+        minute: u8,
+    },
+    Sunrise {
+        #[serde(default)]
+        offset_min: i16,
+    },
+    Sunset {
+        #[serde(default)]
+        offset_min: i16,
+    },
+}
+
+impl Trigger {
+    /// 在某一天触发的时刻，从本地零点开始的分钟数
+    ///
+    /// 日出日落规则在没有配置位置、极昼极夜或者偏移后落到前后一天时，这一天不触发。
+    pub fn minute_of_day(&self, day: &LocalTime, location: Option<&Location>) -> Option<u16> {
+        let (base, offset) = match *self {
+            Trigger::Time { hour, minute } => {
+                return Some((hour as u16) * 60 + (minute as u16));
+            }
+            Trigger::Sunrise { offset_min } => {
+                (location?.sun_times(day.ordinal, day.utc_offset)?.sunrise, offset_min)
+            }
+            Trigger::Sunset { offset_min } => {
+                (location?.sun_times(day.ordinal, day.utc_offset)?.sunset, offset_min)
+            }
+        };
+        let minute = (base as i32) + (offset as i32);
+        (0..24 * 60).contains(&minute).then_some(minute as u16)
+    }
+}
+
+/// 规则触发时执行的动作
+///
+/// ```json
+/// { "type": "scene", "name": "evening" }
+/// { "type": "color", "color": { "kelvin": 2700 }, "brightness": 102, "transition": { "duration_ms": 600000 } }
+/// { "type": "off" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// 恢复保存的场景
+    Scene {
+        name: String,
+    },
+    /// 渐变到纯色，`brightness`为空时保持当前亮度
+    Color {
+        color: ColorSpec,
+        #[serde(default)]
+        brightness: Option<u8>,
+        #[serde(default)]
+        transition: Transition,
+    },
+    /// 渐变到熄灭
+    ///
+    /// 灯带驱动归灯效线程所有，定时任务拿不到`WS2812RMT`，不会调用`WS2812RMT::shutdown`：
+    /// 这里的“关灯”是通过`EffectHandle::fade_to`渐变到黑色，最后的效果与`shutdown`相同，
+    /// 都是所有灯珠熄灭，灯效线程继续运行，之后的动作可以直接点亮。
+    Off {
+        #[serde(default)]
+        transition: Transition,
+    },
+}
+
+impl Action {
+    /// 执行动作，`load_scene`按名字读取保存的场景
+    pub fn apply(
+        &self,
+        handle: &EffectHandle,
+        load_scene: impl FnOnce(&str) -> Result<Option<Scene>>
+    ) -> Result<()> {
+        match self {
+            Action::Scene { name } => {
+                let Some(scene) = load_scene(name)? else {
+                    bail!("scene {:?} not found", name);
+                };
+                handle.apply_scene(&scene)
+            }
+            Action::Color { color, brightness, transition } => {
+                if let Some(brightness) = brightness {
+                    handle.set_brightness(*brightness)?;
+                }
+                handle.fade_to(color.to_rgb(), *transition)
+            }
+            Action::Off { transition } => handle.fade_to(RGB8::default(), *transition),
+        }
+    }
+}
+
+/// 定时规则，`days`为空表示每天
+///
+/// ```json
+/// {
+///   "name": "wake up",
+///   "days": ["mon", "tue", "wed", "thu", "fri"],
+///   "trigger": { "type": "time", "hour": 7, "minute": 0 },
+///   "action": { "type": "color", "color": { "kelvin": 4000 }, "brightness": 255, "transition": { "duration_ms": 600000 } }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub trigger: Trigger,
+    pub action: Action,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Rule {
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LEN {
+            bail!("rule name must be 1-{} bytes, got {:?}", MAX_NAME_LEN, self.name);
+        }
+        if let Trigger::Time { hour, minute } = self.trigger {
+            if hour > 23 || minute > 59 {
+                bail!("invalid time {:02}:{:02}", hour, minute);
+            }
+        }
+        Ok(())
+    }
+
+    /// 规则是否在这一天生效
+    pub fn runs_on(&self, day: &LocalTime) -> bool {
+        self.enabled && (self.days.is_empty() || self.days.iter().any(|d| d.number() == day.weekday))
+    }
+}
+
+/// 所有规则和计算日出日落用的位置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub location: Option<Location>,
+    pub rules: Vec<Rule>,
+}
+
+impl Schedule {
+    pub fn validate(&self) -> Result<()> {
+        if self.rules.len() > MAX_RULES {
+            bail!("at most {} rules", MAX_RULES);
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            rule.validate()?;
+            if self.rules[..i].iter().any(|r| r.name == rule.name) {
+                bail!("duplicate rule {:?}", rule.name);
+            }
+        }
+        if let Some(location) = &self.location {
+            if !location.is_valid() {
+                bail!("invalid location {:?}", location);
+            }
+        }
+        Ok(())
+    }
+
+    /// 添加规则，同名的规则会被替换
+    pub fn upsert(&mut self, rule: Rule) -> Result<()> {
+        rule.validate()?;
+        match self.rules.iter_mut().find(|r| r.name == rule.name) {
+            Some(existing) => {
+                *existing = rule;
+            }
+            None => {
+                if self.rules.len() >= MAX_RULES {
+                    bail!("at most {} rules", MAX_RULES);
+                }
+                self.rules.push(rule);
+            }
+        }
+        Ok(())
+    }
+
+    /// 删除规则，返回是否存在
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|r| r.name != name);
+        self.rules.len() != len
+    }
+
+    /// 某一天的日出日落时间，没有配置位置时返回`None`
+    pub fn sun_times(&self, day: &LocalTime) -> Option<SunTimes> {
+        self.location?.sun_times(day.ordinal, day.utc_offset)
+    }
+
+    /// 在`(prev, now]`之间到期的规则，按触发时刻排序
+    ///
+    /// 时间倒退或者前进超过10分钟时认为时钟发生了跳变，不触发任何规则，
+    /// 避免第一次同步时间时把当天已经过去的规则都执行一遍。
+    pub fn due(&self, prev: &LocalTime, now: &LocalTime) -> Vec<&Rule> {
+        let minutes = |t: &LocalTime| t.days() * 24 * 60 + (t.minute_of_day() as i64);
+        let (from, to) = (minutes(prev), minutes(now));
+        if to <= from || to - from > MAX_CATCH_UP_MINUTES {
+            return Vec::new();
+        }
+
+        let mut due = Vec::new();
+        for days in prev.days()..=now.days() {
+            // 这一天的星期和一年中的第几天，时区偏移使用当前的
+            let day = LocalTime { utc_offset: now.utc_offset, ..LocalTime::from_unix(days * 86400, 0) };
+            for rule in self.rules.iter().filter(|rule| rule.runs_on(&day)) {
+                let Some(minute) = rule.trigger.minute_of_day(&day, self.location.as_ref()) else {
+                    continue;
+                };
+                let at = days * 24 * 60 + (minute as i64);
+                if from < at && at <= to {
+                    due.push((at, rule));
+                }
+            }
+        }
+        due.sort_by_key(|(at, _)| *at);
+        due.into_iter()
+            .map(|(_, rule)| rule)
+            .collect()
+    }
+}
+
+/// 定时任务线程的句柄，可以克隆后交给HTTP修改规则
+#[derive(Clone)]
+pub struct Scheduler {
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl Scheduler {
+    /// 启动定时任务线程，规则到期时调用`run`
+    pub fn spawn(
+        clock: Clock,
+        schedule: Schedule,
+        mut run: impl FnMut(&Rule) -> Result<()> + Send + 'static
+    ) -> Result<Self> {
+        let schedule = Arc::new(Mutex::new(schedule));
+        let shared = schedule.clone();
+        std::thread::Builder
+            ::new()
+            .name("scheduler".into())
+            .stack_size(8 * 1024)
+            .spawn(move || {
+                let mut prev: Option<LocalTime> = None;
+                loop {
+                    std::thread::sleep(Duration::from_secs(1));
+                    // 时间没有同步之前不检查
+                    let Some(now) = clock.local_now() else {
+                        continue;
+                    };
+                    if let Some(prev) = prev {
+                        let due: Vec<Rule> = shared
+                            .lock()
+                            .unwrap()
+                            .due(&prev, &now)
+                            .into_iter()
+                            .cloned()
+                            .collect();
+                        for rule in due {
+                            log::info!("Run scheduled rule {:?}", rule.name);
+                            if let Err(e) = run(&rule) {
+                                log::error!("Failed to run rule {:?}: {}", rule.name, e);
+                            }
+                        }
+                    }
+                    prev = Some(now);
+                }
+            })?;
+        Ok(Self { schedule })
+    }
+
+    /// 当前的规则
+    pub fn schedule(&self) -> Schedule {
+        self.schedule.lock().unwrap().clone()
+    }
+
+    /// 替换所有规则，下一次检查时生效
+    pub fn set_schedule(&self, schedule: Schedule) {
+        *self.schedule.lock().unwrap() = schedule;
+    }
+}
+
+#[cfg(target_os = "espidf")]
+pub use store::ScheduleStore;
+
+#[cfg(target_os = "espidf")]
+mod store {
+    use anyhow::Result;
+    use esp_idf_svc::nvs::{ EspDefaultNvsPartition, EspNvs, NvsDefault };
+
+    use super::Schedule;
+    use crate::storage::{ read_json, write_json };
+
+    const NAMESPACE: &str = "schedule";
+    const SCHEDULE_KEY: &str = "schedule";
+
+    /// 保存在NVS中的定时规则
+    pub struct ScheduleStore {
+        nvs: EspNvs<NvsDefault>,
+    }
+
+    impl ScheduleStore {
+        pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+            Ok(Self { nvs: EspNvs::new(partition, NAMESPACE, true)? })
+        }
+
+        /// 没有保存过时没有任何规则
+        pub fn schedule(&self) -> Result<Schedule> {
+            Ok(read_json(&self.nvs, SCHEDULE_KEY)?.unwrap_or_default())
+        }
+
+        pub fn set_schedule(&mut self, schedule: &Schedule) -> Result<()> {
+            schedule.validate()?;
+            write_json(&mut self.nvs, SCHEDULE_KEY, schedule)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-06-20 星期四 的本地时间，东八区
+    fn at(day: u8, hour: u8, minute: u8) -> LocalTime {
+        local(unix(day, hour, minute))
+    }
+
+    /// 东八区本地时间对应的Unix时间
+    fn unix(day: u8, hour: u8, minute: u8) -> i64 {
+        let secs = (days_since_epoch(day) * 86400) + (hour as i64) * 3600 + (minute as i64) * 60;
+        secs - 8 * 3600
+    }
+
+    fn local(secs: i64) -> LocalTime {
+        LocalTime::from_unix(secs, 8 * 3600)
+    }
+
+    fn days_since_epoch(day: u8) -> i64 {
+        crate::clock::days_from_civil(2024, 6, day)
+    }
+
+    fn rule(name: &str, days: Vec<Weekday>, trigger: Trigger) -> Rule {
+        Rule {
+            name: name.into(),
+            enabled: true,
+            days,
+            trigger,
+            action: Action::Off { transition: Transition::default() },
+        }
+    }
+
+    fn names(rules: Vec<&Rule>) -> Vec<&str> {
+        rules
+            .into_iter()
+            .map(|r| r.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn fires_rules_between_checks() {
+        let schedule = Schedule {
+            location: None,
+            rules: vec![
+                rule("night", vec![], Trigger::Time { hour: 0, minute: 0 }),
+                rule("late", vec![], Trigger::Time { hour: 23, minute: 59 }),
+                rule("wake", Weekday::WORKDAYS.to_vec(), Trigger::Time { hour: 7, minute: 0 })
+            ],
+        };
+        assert_eq!(at(20, 7, 0).weekday, 4);
+        assert_eq!(names(schedule.due(&at(20, 6, 59), &at(20, 7, 0))), ["wake"]);
+        // 同一分钟内再次检查不会重复触发
+        assert!(schedule.due(&at(20, 7, 0), &at(20, 7, 0)).is_empty());
+        // 跨过午夜时按触发时刻排序
+        assert_eq!(names(schedule.due(&at(20, 23, 58), &at(21, 0, 1))), ["late", "night"]);
+        // 6月22日是星期六
+        assert!(schedule.due(&at(22, 6, 59), &at(22, 7, 0)).is_empty());
+        // 时钟跳变或倒退时不触发
+        assert!(schedule.due(&at(20, 0, 0), &at(20, 7, 0)).is_empty());
+        assert!(schedule.due(&at(20, 7, 1), &at(20, 6, 59)).is_empty());
+    }
+
+    #[test]
+    fn sun_rules_need_location() {
+        let mut schedule = Schedule {
+            location: None,
+            rules: vec![rule("dusk", vec![], Trigger::Sunset { offset_min: -15 })],
+        };
+        let sunset = Location { latitude: 39.9, longitude: 116.4 }.sun_times(at(20, 0, 0).ordinal, 8 * 3600).unwrap().sunset;
+        // 日落前15分钟，用Unix时间前后各取一分钟，避免整点附近的分钟数越界
+        let dusk = unix(20, 0, 0) + ((sunset as i64) - 15) * 60;
+        let (before, after) = (local(dusk - 60), local(dusk + 60));
+        assert!(schedule.due(&before, &local(dusk)).is_empty());
+
+        schedule.location = Some(Location { latitude: 39.9, longitude: 116.4 });
+        assert_eq!(schedule.due(&before, &local(dusk)).len(), 1);
+        assert!(schedule.due(&local(dusk), &after).is_empty());
+    }
+
+    #[test]
+    fn validates_rules() {
+        let mut schedule = Schedule::default();
+        schedule.upsert(rule("a", vec![], Trigger::Time { hour: 23, minute: 0 })).unwrap();
+        schedule.upsert(rule("a", vec![], Trigger::Time { hour: 22, minute: 0 })).unwrap();
+        assert_eq!(schedule.rules.len(), 1);
+        assert!(schedule.upsert(rule("b", vec![], Trigger::Time { hour: 24, minute: 0 })).is_err());
+        assert!(schedule.upsert(rule("", vec![], Trigger::Time { hour: 0, minute: 0 })).is_err());
+        assert!(schedule.remove("a"));
+        assert!(!schedule.remove("a"));
+    }
+}
@@ -0,0 +1,101 @@
+//! 日出日落时间
+//!
+//! 使用NOAA的简化公式在本地计算，误差在几分钟以内，不需要联网。
+use std::f64::consts::PI;
+
+use serde::{ Deserialize, Serialize };
+
+/// 设备所在的位置，北纬和东经为正
+///
+/// ```json
+/// { "latitude": 39.9, "longitude": 116.4 }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// 某一天的日出和日落时间，从本地零点开始的分钟数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SunTimes {
+    pub sunrise: u16,
+    pub sunset: u16,
+}
+
+impl Location {
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.latitude) && (-180.0..=180.0).contains(&self.longitude)
+    }
+
+    /// 一年中第`ordinal`天（1月1日为1）的日出日落时间，`utc_offset`为本地时间与UTC的偏移（秒）
+    ///
+    /// 极昼或极夜时没有日出日落，返回`None`。
+    pub fn sun_times(&self, ordinal: u16, utc_offset: i32) -> Option<SunTimes> {
+        // 以当天正午计算的年角
+        let gamma = (2.0 * PI) / 365.0 * ((ordinal as f64) - 1.0);
+        let eq_time =
+            229.18 *
+            (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin() -
+                0.014615 * (2.0 * gamma).cos() -
+                0.040849 * (2.0 * gamma).sin());
+        let decl =
+            0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin() -
+            0.006758 * (2.0 * gamma).cos() +
+            0.000907 * (2.0 * gamma).sin() -
+            0.002697 * (3.0 * gamma).cos() +
+            0.00148 * (3.0 * gamma).sin();
+
+        // 考虑大气折射和太阳视半径，太阳中心在地平线下0.833°时算作日出日落
+        let lat = self.latitude.to_radians();
+        let cos_ha =
+            (90.833f64).to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+        if !(-1.0..=1.0).contains(&cos_ha) {
+            return None;
+        }
+        let ha = cos_ha.acos().to_degrees();
+
+        let offset = (utc_offset as f64) / 60.0;
+        let to_local = |utc_minutes: f64| {
+            (utc_minutes + offset).rem_euclid(24.0 * 60.0).round() as u16 % (24 * 60)
+        };
+        Some(SunTimes {
+            sunrise: to_local(720.0 - 4.0 * (self.longitude + ha) - eq_time),
+            sunset: to_local(720.0 - 4.0 * (self.longitude - ha) - eq_time),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: u16, hour: u16, minute: u16) {
+        let expected = hour * 60 + minute;
+        assert!(actual.abs_diff(expected) <= 5, "expected about {}:{:02}, got {}:{:02}", hour, minute, actual / 60, actual % 60);
+    }
+
+    #[test]
+    fn beijing_summer_solstice() {
+        let beijing = Location { latitude: 39.9, longitude: 116.4 };
+        let times = beijing.sun_times(172, 8 * 3600).unwrap();
+        assert_near(times.sunrise, 4, 46);
+        assert_near(times.sunset, 19, 46);
+    }
+
+    #[test]
+    fn london_winter() {
+        let london = Location { latitude: 51.5, longitude: -0.13 };
+        // 12月21日
+        let times = london.sun_times(355, 0).unwrap();
+        assert_near(times.sunrise, 8, 4);
+        assert_near(times.sunset, 15, 54);
+    }
+
+    #[test]
+    fn polar_night_has_no_sunrise() {
+        let svalbard = Location { latitude: 78.2, longitude: 15.6 };
+        assert_eq!(svalbard.sun_times(355, 3600), None);
+        assert!(!(Location { latitude: 91.0, longitude: 0.0 }).is_valid());
+    }
+}
@@ -0,0 +1,22 @@
+//! 以JSON的形式把数据保存为NVS中的blob，各个模块的Store共用
+use anyhow::Result;
+use esp_idf_svc::nvs::{ EspNvs, NvsDefault };
+use serde::{ de::DeserializeOwned, Serialize };
+
+/// 读取`key`中保存的值，没有保存过时返回`None`
+pub fn read_json<T: DeserializeOwned>(nvs: &EspNvs<NvsDefault>, key: &str) -> Result<Option<T>> {
+    let Some(len) = nvs.blob_len(key)? else {
+        return Ok(None);
+    };
+    let mut buf = vec![0; len];
+    match nvs.get_blob(key, &mut buf)? {
+        Some(data) => Ok(Some(serde_json::from_slice(data)?)),
+        None => Ok(None),
+    }
+}
+
+/// 把值序列化成JSON保存到`key`中，覆盖原来的值
+pub fn write_json<T: Serialize + ?Sized>(nvs: &mut EspNvs<NvsDefault>, key: &str, value: &T) -> Result<()> {
+    nvs.set_blob(key, &serde_json::to_vec(value)?)?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+/// 默认的热点SSID模板
+pub const DEFAULT_AP_SSID: &str = "RustLED-{mac}";
+const MAX_SSID_LEN: usize = 32;
+
+/// 本地控制热点的配置
+///
+/// 开启后设备在连接上游网络的同时始终开启这个热点，没有上游网络时手机也能连上来控制。
+/// 热点上可以访问所有HTTP接口，所以默认关闭，开启时必须设置WPA2密码。
+///
+/// ```json
+/// { "enabled": true, "ssid": "RustLED-{mac}", "password": "12345678", "channel": 6 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApSettings {
+    pub enabled: bool,
+    /// SSID模板，`{mac}`会替换为MAC地址的后三个字节
+    pub ssid: String,
+    /// WPA2密码，长度为8到64，开启热点时不能为空
+    pub password: String,
+    /// 连上上游网络后热点会跟随上游网络的信道
+    pub channel: u8,
+    pub max_connections: u16,
+}
+
+impl Default for ApSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ssid: DEFAULT_AP_SSID.into(),
+            password: String::new(),
+            channel: 1,
+            max_connections: 4,
+        }
+    }
+}
+
+impl ApSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.ssid.is_empty() {
+            bail!("AP SSID is empty");
+        }
+        if self.enabled && self.password.is_empty() {
+            bail!("AP password is required when the AP is enabled");
+        }
+        if !self.password.is_empty() && !(8..=64).contains(&self.password.len()) {
+            bail!("AP password must be 8 to 64 characters");
+        }
+        if !(1..=13).contains(&self.channel) {
+            bail!("invalid AP channel {}", self.channel);
+        }
+        Ok(())
+    }
+
+    /// 用MAC地址展开SSID模板，超过32字节的部分会被截掉
+    pub fn ssid(&self, mac: &[u8; 6]) -> String {
+        let suffix = format!("{:02X}{:02X}{:02X}", mac[3], mac[4], mac[5]);
+        let mut ssid = self.ssid.replace("{mac}", &suffix);
+        if ssid.len() > MAX_SSID_LEN {
+            let mut end = MAX_SSID_LEN;
+            while !ssid.is_char_boundary(end) {
+                end -= 1;
+            }
+            ssid.truncate(end);
+        }
+        ssid
+    }
+
+    /// 生成ESP-IDF的热点配置
+    #[cfg(target_os = "espidf")]
+    pub fn configuration(
+        &self,
+        mac: &[u8; 6]
+    ) -> Result<esp_idf_svc::wifi::AccessPointConfiguration> {
+        use anyhow::anyhow;
+        use esp_idf_svc::wifi::{ AccessPointConfiguration, AuthMethod };
+
+        self.validate()?;
+        let ssid = self.ssid(mac);
+        Ok(AccessPointConfiguration {
+            ssid: ssid.as_str().try_into().map_err(|_| anyhow!("SSID too long: {}", ssid))?,
+            // 热点上可以访问所有接口，不提供开放网络
+            auth_method: AuthMethod::WPA2Personal,
+            password: self.password.as_str().try_into().map_err(|_| anyhow!("password too long"))?,
+            channel: self.channel,
+            max_connections: self.max_connections,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssid_template() {
+        let mac = [0x24, 0x0a, 0xc4, 0xa1, 0xb2, 0xc3];
+        assert_eq!(ApSettings::default().ssid(&mac), "RustLED-A1B2C3");
+        let long = ApSettings { ssid: "灯".repeat(11), ..Default::default() };
+        assert_eq!(long.ssid(&mac), "灯".repeat(10));
+    }
+
+    #[test]
+    fn validates_password_and_channel() {
+        assert!(!ApSettings::default().enabled);
+        assert!(ApSettings::default().validate().is_ok());
+        // 开启热点时必须设置密码
+        let open = ApSettings { enabled: true, ..Default::default() };
+        assert!(open.validate().is_err());
+        assert!((ApSettings { password: "12345678".into(), ..open }).validate().is_ok());
+        assert!((ApSettings { password: "short".into(), ..Default::default() }).validate().is_err());
+        assert!((ApSettings { channel: 14, ..Default::default() }).validate().is_err());
+    }
+}
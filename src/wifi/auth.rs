@@ -0,0 +1,208 @@
+use anyhow::{ bail, Result };
+use serde::{ Deserialize, Serialize };
+
+/// AP使用的认证方式，与ESP-IDF的`AuthMethod`一一对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    Open,
+    Wep,
+    Wpa,
+    Wpa2Personal,
+    WpaWpa2Personal,
+    Wpa2Enterprise,
+    Wpa3Personal,
+    /// WPA2/WPA3过渡模式，两种都可以连接
+    Wpa2Wpa3Personal,
+    WapiPersonal,
+}
+
+/// 企业认证的EAP方法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EapMethod {
+    #[default]
+    Peap,
+    /// TTLS，第二阶段使用MSCHAPv2
+    Ttls,
+}
+
+/// WPA2企业版的凭据，密码使用`KnownNetwork::password`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Enterprise {
+    pub method: EapMethod,
+    /// 外层（匿名）身份，为空时使用`username`
+    pub identity: String,
+    pub username: String,
+}
+
+/// 网络的安全配置，默认根据扫描结果自动选择
+///
+/// ```json
+/// { "type": "wpa3_personal" }
+/// { "type": "enterprise", "method": "ttls", "identity": "anonymous", "username": "alice" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Security {
+    #[default]
+    Auto,
+    Open,
+    Wpa2Personal,
+    Wpa3Personal,
+    Enterprise(Enterprise),
+}
+
+impl Security {
+    /// 结合扫描到的认证方式确定连接时使用的认证方式
+    ///
+    /// `Auto`时使用扫描结果；没有扫描到（隐藏网络）时有密码按WPA2，没有密码按开放网络。
+    pub fn resolve(&self, password: &str, scanned: Option<AuthMode>) -> Result<AuthMode> {
+        let mode = match self {
+            Security::Auto => {
+                match scanned {
+                    Some(AuthMode::Wpa2Enterprise) => {
+                        bail!("network requires enterprise credentials");
+                    }
+                    Some(mode) => mode,
+                    None if password.is_empty() => AuthMode::Open,
+                    None => AuthMode::Wpa2Personal,
+                }
+            }
+            Security::Open => AuthMode::Open,
+            Security::Wpa2Personal => AuthMode::Wpa2Personal,
+            Security::Wpa3Personal => AuthMode::Wpa3Personal,
+            Security::Enterprise(enterprise) => {
+                // 外层身份为空时回退到用户名，所以只需检查用户名
+                if enterprise.username.is_empty() {
+                    bail!("enterprise network requires a username");
+                }
+                AuthMode::Wpa2Enterprise
+            }
+        };
+        if mode != AuthMode::Open && password.is_empty() {
+            bail!("{:?} requires a password", mode);
+        }
+        Ok(mode)
+    }
+}
+
+#[cfg(target_os = "espidf")]
+mod esp {
+    use anyhow::Result;
+    use esp_idf_svc::{ sys::{ self, esp }, wifi::AuthMethod };
+
+    use super::{ AuthMode, EapMethod, Enterprise };
+
+    impl From<AuthMethod> for AuthMode {
+        fn from(method: AuthMethod) -> Self {
+            match method {
+                AuthMethod::None => AuthMode::Open,
+                AuthMethod::WEP => AuthMode::Wep,
+                AuthMethod::WPA => AuthMode::Wpa,
+                AuthMethod::WPA2Personal => AuthMode::Wpa2Personal,
+                AuthMethod::WPAWPA2Personal => AuthMode::WpaWpa2Personal,
+                AuthMethod::WPA2Enterprise => AuthMode::Wpa2Enterprise,
+                AuthMethod::WPA3Personal => AuthMode::Wpa3Personal,
+                AuthMethod::WPA2WPA3Personal => AuthMode::Wpa2Wpa3Personal,
+                AuthMethod::WAPIPersonal => AuthMode::WapiPersonal,
+            }
+        }
+    }
+
+    impl From<AuthMode> for AuthMethod {
+        fn from(mode: AuthMode) -> Self {
+            match mode {
+                AuthMode::Open => AuthMethod::None,
+                AuthMode::Wep => AuthMethod::WEP,
+                AuthMode::Wpa => AuthMethod::WPA,
+                AuthMode::Wpa2Personal => AuthMethod::WPA2Personal,
+                AuthMode::WpaWpa2Personal => AuthMethod::WPAWPA2Personal,
+                AuthMode::Wpa2Enterprise => AuthMethod::WPA2Enterprise,
+                AuthMode::Wpa3Personal => AuthMethod::WPA3Personal,
+                AuthMode::Wpa2Wpa3Personal => AuthMethod::WPA2WPA3Personal,
+                AuthMode::WapiPersonal => AuthMethod::WAPIPersonal,
+            }
+        }
+    }
+
+    /// 设置EAP凭据并启用企业认证，必须在`connect`之前调用
+    pub fn enable_enterprise(enterprise: &Enterprise, password: &str) -> Result<()> {
+        let identity = if enterprise.identity.is_empty() {
+            &enterprise.username
+        } else {
+            &enterprise.identity
+        };
+        unsafe {
+            esp!(sys::esp_eap_client_set_identity(identity.as_ptr(), identity.len() as _))?;
+            esp!(
+                sys::esp_eap_client_set_username(
+                    enterprise.username.as_ptr(),
+                    enterprise.username.len() as _
+                )
+            )?;
+            esp!(sys::esp_eap_client_set_password(password.as_ptr(), password.len() as _))?;
+            if enterprise.method == EapMethod::Ttls {
+                esp!(
+                    sys::esp_eap_client_set_ttls_phase2_method(
+                        sys::esp_eap_ttls_phase2_types_ESP_EAP_TTLS_PHASE2_MSCHAPV2
+                    )
+                )?;
+            }
+            esp!(sys::esp_wifi_sta_enterprise_enable())?;
+        }
+        Ok(())
+    }
+
+    /// 关闭企业认证并清除凭据，连接个人版或开放网络前调用
+    pub fn disable_enterprise() -> Result<()> {
+        unsafe {
+            esp!(sys::esp_wifi_sta_enterprise_disable())?;
+            sys::esp_eap_client_clear_identity();
+            sys::esp_eap_client_clear_username();
+            sys::esp_eap_client_clear_password();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "espidf")]
+pub use esp::{ disable_enterprise, enable_enterprise };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_uses_scanned_mode() {
+        let auto = Security::Auto;
+        assert_eq!(auto.resolve("pw", Some(AuthMode::Wpa3Personal)).unwrap(), AuthMode::Wpa3Personal);
+        assert_eq!(auto.resolve("", Some(AuthMode::Open)).unwrap(), AuthMode::Open);
+        // 隐藏网络没有扫描结果
+        assert_eq!(auto.resolve("pw", None).unwrap(), AuthMode::Wpa2Personal);
+        assert_eq!(auto.resolve("", None).unwrap(), AuthMode::Open);
+        assert!(auto.resolve("pw", Some(AuthMode::Wpa2Enterprise)).is_err());
+    }
+
+    #[test]
+    fn explicit_security_overrides_scan() {
+        let enterprise = Security::Enterprise(Enterprise {
+            username: "alice".into(),
+            ..Default::default()
+        });
+        assert_eq!(enterprise.resolve("pw", Some(AuthMode::Open)).unwrap(), AuthMode::Wpa2Enterprise);
+        assert_eq!(Security::Open.resolve("", Some(AuthMode::Wpa2Personal)).unwrap(), AuthMode::Open);
+        assert!(Security::Wpa3Personal.resolve("", None).is_err());
+    }
+
+    #[test]
+    fn enterprise_requires_credentials() {
+        let enterprise = Security::Enterprise(Enterprise {
+            username: "alice".into(),
+            ..Default::default()
+        });
+        assert!(enterprise.resolve("", None).is_err());
+        assert!(Security::Enterprise(Enterprise::default()).resolve("pw", None).is_err());
+    }
+}
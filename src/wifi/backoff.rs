@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use crate::led::effect::Rng;
+
+/// 带随机抖动的指数退避
+///
+/// 第N次重试的基准等待时间为`initial * 2^N`，不超过`max`；
+/// 实际等待时间在基准的一半到基准之间随机取值，避免多台设备在AP重启后同时重连。
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+    rng: Rng,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max, attempt: 0, rng: Rng::default() }
+    }
+
+    /// 使用指定的随机种子，通常是芯片的MAC地址或硬件随机数
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// 连续失败的次数
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+
+    /// 记录一次失败，返回下一次重试前需要等待的时间
+    pub fn next_delay(&mut self) -> Duration {
+        let base = self.initial
+            .saturating_mul(1u32.checked_shl(self.attempt).unwrap_or(u32::MAX))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = (base.as_millis() / 2) as u32;
+        Duration::from_millis((half + self.rng.range(0, half + 1)) as u64)
+    }
+
+    /// 连接成功后重新从`initial`开始
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_exponentially_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(8)).with_seed(7);
+        let bases = [1000, 2000, 4000, 8000, 8000, 8000];
+        for base in bases {
+            let delay = backoff.next_delay().as_millis() as u64;
+            assert!(delay >= base / 2 && delay <= base, "{delay} not in [{}, {base}]", base / 2);
+        }
+        assert_eq!(backoff.attempts(), 6);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut backoff = Backoff::default();
+        for _ in 0..40 {
+            backoff.next_delay();
+        }
+        assert!(backoff.next_delay() <= Duration::from_secs(60));
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
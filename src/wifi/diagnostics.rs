@@ -0,0 +1,89 @@
+use std::time::{ Duration, Instant };
+
+use serde::Serialize;
+
+use super::WifiState;
+
+/// 连接过程的统计，由监督线程更新
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    /// 连接断开后又重新连上的次数
+    pub reconnects: u32,
+    /// 收到断开事件的次数，包括连接失败
+    pub disconnects: u32,
+    pub last_disconnect_reason: Option<u16>,
+    connected_since: Option<Instant>,
+    connected_before: bool,
+}
+
+impl ConnectionStats {
+    pub fn on_connected(&mut self, now: Instant) {
+        // DHCP续租也会再次拿到IP，不算重连
+        if self.connected_since.is_some() {
+            return;
+        }
+        if self.connected_before {
+            self.reconnects += 1;
+        }
+        self.connected_before = true;
+        self.connected_since = Some(now);
+    }
+
+    pub fn on_disconnected(&mut self, reason: u16) {
+        self.disconnects += 1;
+        self.last_disconnect_reason = Some(reason);
+        self.connected_since = None;
+    }
+
+    /// 这次连接已经持续的时间
+    pub fn connected_for(&self, now: Instant) -> Option<Duration> {
+        self.connected_since.map(|since| now.saturating_duration_since(since))
+    }
+}
+
+/// 连接诊断信息的快照，用于现场排查信号覆盖问题
+///
+/// ```json
+/// {
+///   "state": { "state": "connected", "ssid": "office", "ip": "192.168.1.10", "gateway": "192.168.1.1" },
+///   "rssi": -61, "channel": 6, "bssid": "24:0a:c4:a1:b2:c3",
+///   "reconnects": 2, "disconnects": 5, "last_disconnect_reason": 201,
+///   "connected_for_ms": 360000, "uptime_ms": 7200000
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostics {
+    pub state: WifiState,
+    /// 当前连接的AP，没有连接时为`None`
+    pub rssi: Option<i8>,
+    pub channel: Option<u8>,
+    pub bssid: Option<String>,
+    pub reconnects: u32,
+    pub disconnects: u32,
+    pub last_disconnect_reason: Option<u16>,
+    pub connected_for_ms: Option<u64>,
+    /// 设备启动以来的时间
+    pub uptime_ms: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_reconnects_after_first_connection() {
+        let start = Instant::now();
+        let mut stats = ConnectionStats::default();
+        stats.on_disconnected(201);
+        stats.on_connected(start);
+        stats.on_connected(start + Duration::from_secs(1));
+        assert_eq!(stats.reconnects, 0);
+        assert_eq!(stats.connected_for(start + Duration::from_secs(3)), Some(Duration::from_secs(3)));
+
+        stats.on_disconnected(8);
+        assert_eq!(stats.connected_for(start), None);
+        stats.on_connected(start);
+        assert_eq!((stats.reconnects, stats.disconnects), (1, 2));
+        assert_eq!(stats.last_disconnect_reason, Some(8));
+    }
+}
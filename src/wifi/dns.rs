@@ -0,0 +1,191 @@
+use std::{
+    net::{ Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket },
+    sync::{ atomic::{ AtomicBool, Ordering }, Arc },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::Result;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+// 应答的TTL（秒），配网期间不希望手机长时间缓存
+const TTL: u32 = 60;
+
+/// 把所有A记录查询都解析到同一个地址的DNS服务器，用于配网时的强制门户
+///
+/// 手机连上设备的热点后会访问固定的域名检测网络，解析到设备自己后就会弹出配网页面。
+pub struct CaptiveDns {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CaptiveDns {
+    /// 在53端口上启动DNS服务器
+    pub fn start(ip: Ipv4Addr) -> Result<Self> {
+        Self::bind(("0.0.0.0", 53), ip)
+    }
+
+    /// 在指定地址上启动DNS服务器，所有A记录都解析为`ip`
+    pub fn bind(addr: impl ToSocketAddrs, ip: Ipv4Addr) -> Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        // 定时醒来检查是否需要停止
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+        let addr = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::Builder
+                ::new()
+                .name("captive-dns".into())
+                .stack_size(4096)
+                .spawn(move || serve(socket, ip, &stop))?
+        };
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for CaptiveDns {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(socket: UdpSocket, ip: Ipv4Addr, stop: &AtomicBool) {
+    let mut buf = [0u8; 512];
+    while !stop.load(Ordering::Relaxed) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(_) => {
+                continue;
+            }
+        };
+        if let Some(response) = answer(&buf[..len], ip) {
+            if let Err(e) = socket.send_to(&response, peer) {
+                log::warn!("Failed to send DNS response: {}", e);
+            }
+        }
+    }
+}
+
+/// 生成DNS查询的应答：A记录解析为`ip`，其它类型返回空应答，不是标准查询时返回`None`
+pub fn answer(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    // 只回答标准查询（QR=0，OPCODE=0）
+    if flags & 0x8000 != 0 || (flags >> 11) & 0xf != 0 {
+        return None;
+    }
+    if u16::from_be_bytes([query[4], query[5]]) != 1 {
+        return None;
+    }
+
+    // 跳过问题中的域名，问题里不应该出现压缩指针
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *query.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 {
+            return None;
+        }
+        pos += len;
+    }
+    let question_end = pos + 4;
+    if question_end > query.len() {
+        return None;
+    }
+    let qtype = u16::from_be_bytes([query[pos], query[pos + 1]]);
+    let qclass = u16::from_be_bytes([query[pos + 2], query[pos + 3]]) & 0x7fff;
+    let is_a = qtype == TYPE_A && qclass == CLASS_IN;
+
+    let mut response = Vec::with_capacity(question_end + 16);
+    response.extend_from_slice(&query[..2]);
+    // QR=1、AA=1、RA=1，保留查询中的RD
+    response.extend_from_slice(&(0x8480 | (flags & 0x0100)).to_be_bytes());
+    response.extend_from_slice(&[0, 1, 0, is_a as u8, 0, 0, 0, 0]);
+    response.extend_from_slice(&query[HEADER_LEN..question_end]);
+    if is_a {
+        // 名字用指向问题的压缩指针
+        response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        response.extend_from_slice(&TYPE_A.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&TTL.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip.octets());
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut q = vec![0xab, 0xcd, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            q.push(label.len() as u8);
+            q.extend_from_slice(label.as_bytes());
+        }
+        q.push(0);
+        q.extend_from_slice(&qtype.to_be_bytes());
+        q.extend_from_slice(&CLASS_IN.to_be_bytes());
+        q
+    }
+
+    #[test]
+    fn a_record_resolves_to_device() {
+        let q = query("connectivitycheck.gstatic.com", TYPE_A);
+        let r = answer(&q, IP).unwrap();
+        assert_eq!(&r[..2], &[0xab, 0xcd]);
+        assert_eq!(r[2] & 0x80, 0x80);
+        // 一个问题，一个回答
+        assert_eq!(&r[4..8], &[0, 1, 0, 1]);
+        assert_eq!(&r[12..q.len()], &q[12..]);
+        assert_eq!(&r[r.len() - 4..], &IP.octets());
+    }
+
+    #[test]
+    fn other_types_get_empty_answer() {
+        let q = query("example.com", 28);
+        let r = answer(&q, IP).unwrap();
+        assert_eq!(&r[6..8], &[0, 0]);
+        assert_eq!(r.len(), q.len());
+    }
+
+    #[test]
+    fn malformed_queries_are_ignored() {
+        let q = query("example.com", TYPE_A);
+        assert_eq!(answer(&q[..q.len() - 2], IP), None);
+        assert_eq!(answer(&q[..5], IP), None);
+        let mut response = q.clone();
+        response[2] |= 0x80;
+        assert_eq!(answer(&response, IP), None);
+    }
+
+    #[test]
+    fn serves_over_udp() {
+        let dns = CaptiveDns::bind("127.0.0.1:0", IP).unwrap();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        client.send_to(&query("example.com", TYPE_A), dns.local_addr()).unwrap();
+        let mut buf = [0u8; 512];
+        let len = client.recv(&mut buf).unwrap();
+        assert_eq!(&buf[len - 4..len], &IP.octets());
+    }
+}
@@ -0,0 +1,192 @@
+use std::net::Ipv4Addr;
+
+use anyhow::{ anyhow, bail, Result };
+use serde::{ Deserialize, Serialize };
+
+/// 默认的主机名模板
+pub const DEFAULT_HOSTNAME: &str = "rustled-{mac}";
+// ESP-IDF中DHCP主机名的最大长度
+const MAX_HOSTNAME_LEN: usize = 30;
+
+/// 站点接口的IP配置，默认使用DHCP
+///
+/// ```json
+/// { "hostname": "lab-led-{mac}" }
+/// { "static_ip": { "ip": "10.0.0.20", "netmask": "255.255.255.0", "gateway": "10.0.0.1", "dns": ["10.0.0.1"] } }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpSettings {
+    /// 主机名模板，`{mac}`会替换为MAC地址的后三个字节
+    pub hostname: String,
+    /// 为`None`时使用DHCP
+    pub static_ip: Option<StaticIp>,
+}
+
+impl Default for IpSettings {
+    fn default() -> Self {
+        Self { hostname: DEFAULT_HOSTNAME.into(), static_ip: None }
+    }
+}
+
+/// 固定的IPv4地址
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticIp {
+    pub ip: Ipv4Addr,
+    pub netmask: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    /// 最多使用前两个
+    #[serde(default)]
+    pub dns: Vec<Ipv4Addr>,
+}
+
+impl StaticIp {
+    /// 子网掩码的前缀长度，掩码不连续时返回错误
+    pub fn prefix_len(&self) -> Result<u8> {
+        let mask = u32::from(self.netmask);
+        let len = mask.leading_ones();
+        if mask.checked_shl(len).unwrap_or(0) != 0 {
+            bail!("invalid netmask {}", self.netmask);
+        }
+        Ok(len as u8)
+    }
+}
+
+impl IpSettings {
+    /// 从配置文件中的字符串生成，`ip`为空时使用DHCP，`dns`用逗号分隔
+    pub fn from_config(
+        hostname: &str,
+        ip: &str,
+        netmask: &str,
+        gateway: &str,
+        dns: &str
+    ) -> Result<Self> {
+        let hostname = if hostname.is_empty() { DEFAULT_HOSTNAME } else { hostname };
+        let static_ip = if ip.is_empty() {
+            None
+        } else {
+            let parse = |name: &str, value: &str| {
+                value.trim().parse::<Ipv4Addr>().map_err(|_| anyhow!("invalid {}: {:?}", name, value))
+            };
+            Some(StaticIp {
+                ip: parse("ip", ip)?,
+                netmask: parse("netmask", netmask)?,
+                gateway: parse("gateway", gateway)?,
+                dns: dns
+                    .split(',')
+                    .filter(|s| !s.trim().is_empty())
+                    .map(|s| parse("dns", s))
+                    .collect::<Result<_>>()?,
+            })
+        };
+        let settings = Self { hostname: hostname.into(), static_ip };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(static_ip) = &self.static_ip {
+            static_ip.prefix_len()?;
+        }
+        Ok(())
+    }
+
+    /// 用MAC地址展开主机名模板
+    ///
+    /// 只保留字母、数字和`-`，转换为小写，超出长度的部分会被截掉。
+    pub fn hostname(&self, mac: &[u8; 6]) -> String {
+        let suffix = format!("{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]);
+        let hostname: String = self.hostname
+            .replace("{mac}", &suffix)
+            .chars()
+            .filter_map(|c| {
+                match c {
+                    'a'..='z' | '0'..='9' | '-' => Some(c),
+                    'A'..='Z' => Some(c.to_ascii_lowercase()),
+                    '_' | ' ' | '.' => Some('-'),
+                    _ => None,
+                }
+            })
+            .take(MAX_HOSTNAME_LEN)
+            .collect();
+        match hostname.trim_matches('-') {
+            "" => format!("esp32-{}", suffix),
+            trimmed => trimmed.to_string(),
+        }
+    }
+}
+
+/// 按配置替换站点接口，必须在Wi-Fi启动之前调用，返回生效的主机名
+#[cfg(target_os = "espidf")]
+pub fn apply(wifi: &mut esp_idf_svc::wifi::EspWifi<'static>, settings: &IpSettings) -> Result<String> {
+    use esp_idf_svc::{
+        ipv4::{ self, ClientSettings, DHCPClientSettings, Mask, Subnet },
+        netif::{ EspNetif, NetifConfiguration },
+    };
+
+    let mac = wifi.sta_netif().get_mac()?;
+    let hostname = settings.hostname(&mac);
+    let ip_configuration = match &settings.static_ip {
+        Some(static_ip) => {
+            log::info!("站点接口使用固定IP: {}/{}", static_ip.ip, static_ip.prefix_len()?);
+            ipv4::ClientConfiguration::Fixed(ClientSettings {
+                ip: static_ip.ip,
+                subnet: Subnet { gateway: static_ip.gateway, mask: Mask(static_ip.prefix_len()?) },
+                dns: static_ip.dns.first().copied(),
+                secondary_dns: static_ip.dns.get(1).copied(),
+            })
+        }
+        None =>
+            ipv4::ClientConfiguration::DHCP(DHCPClientSettings {
+                hostname: Some(hostname.as_str().try_into().map_err(|_| anyhow!("hostname too long"))?),
+            }),
+    };
+    let netif = EspNetif::new_with_conf(
+        &(NetifConfiguration {
+            ip_configuration: Some(ipv4::Configuration::Client(ip_configuration)),
+            ..NetifConfiguration::wifi_default_client()
+        })
+    )?;
+    wifi.swap_netif_sta(netif)?;
+    wifi.sta_netif_mut().set_hostname(&hostname)?;
+    log::info!("主机名: {}", hostname);
+    Ok(hostname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x24, 0x0a, 0xc4, 0xa1, 0xb2, 0xc3];
+
+    #[test]
+    fn hostname_template() {
+        assert_eq!(IpSettings::default().hostname(&MAC), "rustled-a1b2c3");
+        let settings = IpSettings { hostname: "Lab_LED {mac}!".into(), static_ip: None };
+        assert_eq!(settings.hostname(&MAC), "lab-led-a1b2c3");
+        let settings = IpSettings { hostname: "--".into(), static_ip: None };
+        assert_eq!(settings.hostname(&MAC), "esp32-a1b2c3");
+        let settings = IpSettings { hostname: "x".repeat(40), static_ip: None };
+        assert_eq!(settings.hostname(&MAC).len(), MAX_HOSTNAME_LEN);
+    }
+
+    #[test]
+    fn parses_config_strings() {
+        let dhcp = IpSettings::from_config("", "", "", "", "").unwrap();
+        assert_eq!(dhcp, IpSettings::default());
+
+        let fixed = IpSettings::from_config(
+            "lab",
+            "10.0.0.20",
+            "255.255.255.0",
+            "10.0.0.1",
+            "10.0.0.1, 1.1.1.1"
+        ).unwrap();
+        let static_ip = fixed.static_ip.unwrap();
+        assert_eq!(static_ip.prefix_len().unwrap(), 24);
+        assert_eq!(static_ip.dns, [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(1, 1, 1, 1)]);
+
+        assert!(IpSettings::from_config("", "10.0.0.20", "255.0.255.0", "10.0.0.1", "").is_err());
+        assert!(IpSettings::from_config("", "10.0.0.300", "255.255.255.0", "10.0.0.1", "").is_err());
+    }
+}
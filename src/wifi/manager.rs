@@ -0,0 +1,425 @@
+use std::{
+    collections::VecDeque,
+    net::Ipv4Addr,
+    sync::{ mpsc::{ channel, Receiver, RecvTimeoutError, Sender }, Arc, Mutex, MutexGuard },
+    time::{ Duration, Instant },
+};
+
+use anyhow::{ anyhow, Result };
+use esp_idf_svc::{
+    eventloop::{ EspSubscription, EspSystemEventLoop, System },
+    hal::{ modem::Modem, peripheral::Peripheral },
+    netif::IpEvent,
+    nvs::EspDefaultNvsPartition,
+    sys,
+    wifi::{
+        AccessPointConfiguration,
+        AuthMethod,
+        ClientConfiguration,
+        Configuration,
+        EspWifi,
+        PmfConfiguration,
+        WifiEvent,
+    },
+};
+
+use super::{
+    client_configuration,
+    diagnostics::ConnectionStats,
+    scan::format_mac,
+    ApSettings,
+    ip,
+    networks::candidates,
+    scanned_auth,
+    AuthMode,
+    Backoff,
+    Diagnostics,
+    IpSettings,
+    KnownNetwork,
+    Subscriber,
+    Watch,
+    WifiState,
+};
+
+/// 事件回调和管理器转发给监督线程的事件
+enum Event {
+    Started,
+    Disconnected(u16),
+    GotIp(Ipv4Addr, Ipv4Addr),
+    Networks(Vec<KnownNetwork>),
+}
+
+/// Wi-Fi连接管理器
+///
+/// 订阅系统事件循环中的Wi-Fi和IP事件，断开后按指数退避自动重连，
+/// 依赖网络的服务可以通过`subscribe`观察连接状态的变化。
+///
+/// 每轮连接前先扫描，按信号强度依次尝试已知网络，一个失败就换下一个，
+/// 全部失败后再退避等待下一轮。
+///
+/// 用`start_mixed`启动时同时开启本地热点，热点不受站点接口重连的影响。
+/// 服务器监听所有地址，在两个接口上都可以访问。
+pub struct WifiManager {
+    wifi: Arc<Mutex<EspWifi<'static>>>,
+    state: Watch<WifiState>,
+    stats: Arc<Mutex<ConnectionStats>>,
+    tx: Sender<Event>,
+    hostname: String,
+    // 保持事件订阅，管理器被丢弃后监督线程随之退出
+    _wifi_events: EspSubscription<'static, System>,
+    _ip_events: EspSubscription<'static, System>,
+}
+
+impl WifiManager {
+    /// 初始化Wi-Fi并开始连接，立即返回，连接结果通过状态通知
+    pub fn start(
+        modem: impl Peripheral<P = Modem> + 'static,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+        networks: Vec<KnownNetwork>,
+        ip_settings: &IpSettings
+    ) -> Result<Self> {
+        Self::start_with_backoff(modem, sysloop, nvs, networks, ip_settings, None, Backoff::default())
+    }
+
+    /// 以AP+STA混合模式启动，有上游网络时连接上游网络，同时始终开启本地热点
+    ///
+    /// 热点没有启用时与`start`相同。
+    pub fn start_mixed(
+        modem: impl Peripheral<P = Modem> + 'static,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+        networks: Vec<KnownNetwork>,
+        ip_settings: &IpSettings,
+        ap: &ApSettings
+    ) -> Result<Self> {
+        let ap = ap.enabled.then_some(ap);
+        Self::start_with_backoff(modem, sysloop, nvs, networks, ip_settings, ap, Backoff::default())
+    }
+
+    pub fn start_with_backoff(
+        modem: impl Peripheral<P = Modem> + 'static,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition,
+        networks: Vec<KnownNetwork>,
+        ip_settings: &IpSettings,
+        ap: Option<&ApSettings>,
+        backoff: Backoff
+    ) -> Result<Self> {
+        let mut wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+        let hostname = ip::apply(&mut wifi, ip_settings)?;
+        let mac = wifi.sta_netif().get_mac()?;
+        // 保存的热点配置无效（例如旧版本保存的开放热点）时不开启热点，不影响连接上游网络
+        let ap = ap.and_then(|ap| {
+            ap.configuration(&mac)
+                .map_err(|e| log::warn!("热点配置无效，不开启热点: {}", e))
+                .ok()
+        });
+        wifi.set_configuration(&configuration(ClientConfiguration::default(), &ap))?;
+        if let Some(ap) = &ap {
+            log::info!("本地热点: {}，地址: {}", ap.ssid, wifi.ap_netif().get_ip_info()?.ip);
+        }
+        // 用MAC地址作为随机种子，让不同设备的重连时间错开
+        let backoff = backoff.with_seed(u32::from_le_bytes([mac[2], mac[3], mac[4], mac[5]]));
+
+        // 事件回调运行在系统事件循环的任务里，不能阻塞，只把事件转发给监督线程
+        let (tx, rx) = channel();
+        let wifi_tx = tx.clone();
+        let wifi_events = sysloop.subscribe::<WifiEvent, _>(move |event| {
+            let event = match event {
+                WifiEvent::StaStarted => Event::Started,
+                WifiEvent::StaDisconnected(info) => Event::Disconnected(info.reason()),
+                _ => {
+                    return;
+                }
+            };
+            let _ = wifi_tx.send(event);
+        })?;
+        let ip_tx = tx.clone();
+        let ip_events = sysloop.subscribe::<IpEvent, _>(move |event| {
+            if let IpEvent::DhcpIpAssigned(assignment) = event {
+                let _ = ip_tx.send(Event::GotIp(assignment.ip(), assignment.gateway()));
+            }
+        })?;
+
+        let wifi = Arc::new(Mutex::new(wifi));
+        let state = Watch::new(WifiState::Stopped);
+        let stats = Arc::new(Mutex::new(ConnectionStats::default()));
+        let mut supervisor = Supervisor {
+            wifi: wifi.clone(),
+            state: state.clone(),
+            stats: stats.clone(),
+            backoff,
+            rx,
+            networks,
+            ap,
+            queue: VecDeque::new(),
+            current: None,
+        };
+        std::thread::Builder
+            ::new()
+            .name("wifi-supervisor".into())
+            .stack_size(6144)
+            .spawn(move || supervisor.run())?;
+
+        log::info!("启动Wi-Fi");
+        wifi.lock().unwrap().start()?;
+
+        Ok(Self { wifi, state, stats, tx, hostname, _wifi_events: wifi_events, _ip_events: ip_events })
+    }
+
+    /// 当前的连接状态
+    pub fn state(&self) -> WifiState {
+        self.state.get()
+    }
+
+    /// 连接诊断信息的快照，包括当前AP的信号强度和信道、重连次数和上次断开的原因
+    pub fn diagnostics(&self) -> Diagnostics {
+        let state = self.state();
+        let stats = self.stats.lock().unwrap().clone();
+        let ap = if state.is_connected() { current_ap_record() } else { None };
+        Diagnostics {
+            state,
+            rssi: ap.map(|ap| ap.rssi),
+            channel: ap.map(|ap| ap.primary),
+            bssid: ap.map(|ap| format_mac(&ap.bssid)),
+            reconnects: stats.reconnects,
+            disconnects: stats.disconnects,
+            last_disconnect_reason: stats.last_disconnect_reason,
+            connected_for_ms: stats.connected_for(Instant::now()).map(|d| d.as_millis() as u64),
+            uptime_ms: (unsafe { sys::esp_timer_get_time() } / 1000) as u64,
+        }
+    }
+
+    /// 站点接口的主机名
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// 订阅连接状态的变化
+    pub fn subscribe(&self) -> Subscriber<WifiState> {
+        self.state.subscribe()
+    }
+
+    /// 阻塞直到连接成功，返回IP地址，`timeout`为`None`表示一直等待
+    pub fn wait_connected(&self, timeout: Option<Duration>) -> Result<Ipv4Addr> {
+        self.subscribe()
+            .wait_for(WifiState::is_connected, timeout)
+            .and_then(|state| state.ip())
+            .ok_or_else(|| anyhow!("timed out waiting for Wi-Fi"))
+    }
+
+    /// 更新已知网络列表，当前连接的网络不在新列表中时断开重连
+    pub fn set_networks(&self, networks: Vec<KnownNetwork>) -> Result<()> {
+        self.tx.send(Event::Networks(networks)).map_err(|_| anyhow!("Wi-Fi supervisor stopped"))
+    }
+
+    /// 底层的Wi-Fi驱动，持有锁期间监督线程无法重连
+    pub fn wifi(&self) -> MutexGuard<'_, EspWifi<'static>> {
+        self.wifi.lock().unwrap()
+    }
+}
+
+/// 当前连接的AP的信息
+fn current_ap_record() -> Option<sys::wifi_ap_record_t> {
+    let mut record: sys::wifi_ap_record_t = unsafe { std::mem::zeroed() };
+    let result = unsafe { sys::esp_wifi_sta_get_ap_info(&mut record) };
+    (result == sys::ESP_OK).then_some(record)
+}
+
+/// 有热点配置时使用混合模式，否则只启用站点接口
+fn configuration(client: ClientConfiguration, ap: &Option<AccessPointConfiguration>) -> Configuration {
+    match ap {
+        Some(ap) => Configuration::Mixed(client, ap.clone()),
+        None => Configuration::Client(client),
+    }
+}
+
+/// 只修改站点接口的配置，混合模式下热点保持运行，已经连上热点的客户端不会断开
+///
+/// `EspWifi::set_configuration`总是同时设置两个接口，所以这里直接调用ESP-IDF。
+fn set_client_configuration(client: &ClientConfiguration) -> Result<()> {
+    let mut sta: sys::wifi_sta_config_t = unsafe { std::mem::zeroed() };
+    // heapless字符串的容量与ESP-IDF的数组长度一致
+    sta.ssid[..client.ssid.len()].copy_from_slice(client.ssid.as_bytes());
+    sta.password[..client.password.len()].copy_from_slice(client.password.as_bytes());
+    if let Some(bssid) = client.bssid {
+        sta.bssid_set = true;
+        sta.bssid = bssid;
+    }
+    sta.channel = client.channel.unwrap_or(0);
+    sta.scan_method = sys::wifi_scan_method_t_WIFI_FAST_SCAN;
+    sta.sort_method = sys::wifi_sort_method_t_WIFI_CONNECT_AP_BY_SIGNAL;
+    sta.threshold.authmode = match client.auth_method {
+        AuthMethod::None => sys::wifi_auth_mode_t_WIFI_AUTH_OPEN,
+        AuthMethod::WEP => sys::wifi_auth_mode_t_WIFI_AUTH_WEP,
+        AuthMethod::WPA => sys::wifi_auth_mode_t_WIFI_AUTH_WPA_PSK,
+        AuthMethod::WPA2Personal => sys::wifi_auth_mode_t_WIFI_AUTH_WPA2_PSK,
+        AuthMethod::WPAWPA2Personal => sys::wifi_auth_mode_t_WIFI_AUTH_WPA_WPA2_PSK,
+        AuthMethod::WPA2Enterprise => sys::wifi_auth_mode_t_WIFI_AUTH_WPA2_ENTERPRISE,
+        AuthMethod::WPA3Personal => sys::wifi_auth_mode_t_WIFI_AUTH_WPA3_PSK,
+        AuthMethod::WPA2WPA3Personal => sys::wifi_auth_mode_t_WIFI_AUTH_WPA2_WPA3_PSK,
+        AuthMethod::WAPIPersonal => sys::wifi_auth_mode_t_WIFI_AUTH_WAPI_PSK,
+    };
+    (sta.pmf_cfg.capable, sta.pmf_cfg.required) = match client.pmf_cfg {
+        PmfConfiguration::NotCapable => (false, false),
+        PmfConfiguration::Capable { required } => (true, required),
+    };
+    let mut config = sys::wifi_config_t { sta };
+    sys::esp!(unsafe { sys::esp_wifi_set_config(sys::wifi_interface_t_WIFI_IF_STA, &mut config) })?;
+    Ok(())
+}
+
+/// 在后台线程中处理Wi-Fi事件和重连
+struct Supervisor {
+    wifi: Arc<Mutex<EspWifi<'static>>>,
+    state: Watch<WifiState>,
+    stats: Arc<Mutex<ConnectionStats>>,
+    backoff: Backoff,
+    rx: Receiver<Event>,
+    networks: Vec<KnownNetwork>,
+    // 混合模式下的热点配置
+    ap: Option<AccessPointConfiguration>,
+    // 这一轮还没有尝试的网络，以及扫描到的认证方式
+    queue: VecDeque<(KnownNetwork, Option<AuthMode>)>,
+    // 正在连接或者已经连接的网络
+    current: Option<KnownNetwork>,
+}
+
+impl Supervisor {
+    fn run(&mut self) {
+        // 下一次重连的时间
+        let mut retry_at: Option<Instant> = None;
+        loop {
+            let event = match retry_at {
+                Some(at) => {
+                    match self.rx.recv_timeout(at.saturating_duration_since(Instant::now())) {
+                        Ok(event) => Some(event),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return;
+                        }
+                    }
+                }
+                None =>
+                    match self.rx.recv() {
+                        Ok(event) => Some(event),
+                        Err(_) => {
+                            return;
+                        }
+                    }
+            };
+
+            retry_at = match event {
+                // 启动完成或者等待结束，发起连接
+                Some(Event::Started) | None => self.connect(),
+                Some(Event::Disconnected(reason)) => {
+                    self.stats.lock().unwrap().on_disconnected(reason);
+                    Some(self.schedule_retry(reason))
+                }
+                Some(Event::GotIp(ip, gateway)) => {
+                    let ssid = self.current
+                        .as_ref()
+                        .map(|n| n.ssid.clone())
+                        .unwrap_or_default();
+                    log::info!("Wi-Fi已连接到{}，IP: {}", ssid, ip);
+                    self.backoff.reset();
+                    self.queue.clear();
+                    self.stats.lock().unwrap().on_connected(Instant::now());
+                    self.state.set(WifiState::Connected { ssid, ip, gateway });
+                    None
+                }
+                Some(Event::Networks(networks)) => {
+                    self.networks = networks;
+                    self.queue.clear();
+                    self.backoff.reset();
+                    let keep = self.current
+                        .as_ref()
+                        .is_some_and(|current| self.networks.contains(current));
+                    match self.state.get() {
+                        // 还没有启动，等启动事件
+                        WifiState::Stopped => retry_at,
+                        WifiState::Connected { .. } | WifiState::Connecting { .. } if keep => None,
+                        WifiState::Connected { .. } | WifiState::Connecting { .. } => {
+                            // 断开后会收到断开事件，再从新的列表重新开始
+                            if let Err(e) = self.wifi.lock().unwrap().disconnect() {
+                                log::warn!("Failed to disconnect Wi-Fi: {}", e);
+                            }
+                            None
+                        }
+                        // 正在等待重试，不用再等了
+                        _ => Some(Instant::now()),
+                    }
+                }
+            };
+        }
+    }
+
+    /// 扫描并按信号强度排出这一轮要尝试的网络
+    fn refill_queue(&mut self) {
+        let visible = match self.wifi.lock().unwrap().scan() {
+            Ok(aps) => aps,
+            Err(e) => {
+                log::warn!("Wi-Fi扫描失败: {}", e);
+                Vec::new()
+            }
+        };
+        log::info!("扫描到的Wi-Fi数量: {}", visible.len());
+        self.queue = candidates(
+            &self.networks,
+            visible.iter().map(|ap| (ap.ssid.as_str(), ap.signal_strength))
+        )
+            .into_iter()
+            .map(|network| (network.clone(), scanned_auth(&visible, &network.ssid)))
+            .collect();
+    }
+
+    /// 向下一个候选网络发起连接，连接结果通过事件返回；发起失败时直接安排重试
+    fn connect(&mut self) -> Option<Instant> {
+        if self.queue.is_empty() {
+            self.refill_queue();
+        }
+        let Some((network, scanned)) = self.queue.pop_front() else {
+            log::warn!("没有已知的Wi-Fi网络");
+            self.current = None;
+            return Some(self.schedule_retry(0));
+        };
+
+        self.state.set(WifiState::Connecting {
+            ssid: network.ssid.clone(),
+            attempt: self.backoff.attempts() + 1,
+        });
+        log::info!("连接Wi-Fi: {}", network.ssid);
+        let result = client_configuration(&network, scanned).and_then(|client| {
+            let mut wifi = self.wifi.lock().unwrap();
+            if self.ap.is_some() {
+                // 热点的配置在启动时已经设置好，重新设置会重启热点
+                set_client_configuration(&client)?;
+            } else {
+                wifi.set_configuration(&Configuration::Client(client))?;
+            }
+            wifi.connect()?;
+            Ok(())
+        });
+        self.current = Some(network);
+        match result {
+            Ok(()) => None,
+            Err(e) => {
+                log::error!("Failed to start Wi-Fi connection: {}", e);
+                Some(self.schedule_retry(0))
+            }
+        }
+    }
+
+    /// 这一轮还有候选网络时立即尝试下一个，否则按退避等待
+    fn schedule_retry(&mut self, reason: u16) -> Instant {
+        let delay = if self.queue.is_empty() {
+            self.backoff.next_delay()
+        } else {
+            Duration::ZERO
+        };
+        log::warn!("Wi-Fi断开（原因 {}），{}ms后重连", reason, delay.as_millis());
+        self.state.set(WifiState::Disconnected { reason, retry_in_ms: delay.as_millis() as u64 });
+        Instant::now() + delay
+    }
+}
@@ -0,0 +1,164 @@
+use std::net::IpAddr;
+
+use serde::Serialize;
+
+/// 自定义服务的类型，App和其它设备可以用它找到LED控制器
+pub const RUSTLED_SERVICE: &str = "_rustled";
+/// 设备查找OTA服务器时使用的服务类型
+pub const OTA_SERVICE: &str = "_rustled-ota";
+
+/// 服务的TXT记录：固件版本和设备支持的功能
+pub fn txt_records(capabilities: &[&str]) -> Vec<(&'static str, String)> {
+    vec![("version", env!("CARGO_PKG_VERSION").to_string()), ("caps", capabilities.join(","))]
+}
+
+/// 通过mDNS发现的服务
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Discovered {
+    pub instance: Option<String>,
+    pub hostname: Option<String>,
+    pub port: u16,
+    pub addrs: Vec<IpAddr>,
+    pub txt: Vec<(String, String)>,
+}
+
+impl Discovered {
+    pub fn txt(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 服务的HTTP地址，优先使用IPv4，路径优先使用TXT记录中的`path`，没有时使用`default_path`
+    pub fn url(&self, default_path: &str) -> Option<String> {
+        let addr = self.addrs
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or(self.addrs.first())?;
+        let host = match addr {
+            IpAddr::V4(v4) => v4.to_string(),
+            IpAddr::V6(v6) => format!("[{}]", v6),
+        };
+        let path = self.txt("path").unwrap_or(default_path);
+        let slash = if path.starts_with('/') { "" } else { "/" };
+        Some(format!("http://{}:{}{}{}", host, self.port, slash, path))
+    }
+}
+
+#[cfg(target_os = "espidf")]
+pub use responder::Mdns;
+
+#[cfg(target_os = "espidf")]
+mod responder {
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use esp_idf_svc::mdns::{ EspMdns, Interface, Protocol, QueryResult };
+
+    use super::{ txt_records, Discovered, RUSTLED_SERVICE };
+
+    // 一次浏览最多返回的结果数
+    const MAX_RESULTS: usize = 8;
+
+    /// mDNS应答器，在局域网中宣告`<hostname>.local`和设备提供的服务
+    pub struct Mdns {
+        mdns: EspMdns,
+    }
+
+    impl Mdns {
+        /// 启动应答器，宣告`_http._tcp`和`_rustled._tcp`服务
+        pub fn start(hostname: &str, http_port: u16, capabilities: &[&str]) -> Result<Self> {
+            let mut mdns = EspMdns::take()?;
+            mdns.set_hostname(hostname)?;
+            mdns.set_instance_name(&format!("RustLED {}", hostname))?;
+            let txt = txt_records(capabilities);
+            let txt: Vec<(&str, &str)> = txt
+                .iter()
+                .map(|(k, v)| (*k, v.as_str()))
+                .collect();
+            mdns.add_service(None, "_http", "_tcp", http_port, &txt)?;
+            mdns.add_service(None, RUSTLED_SERVICE, "_tcp", http_port, &txt)?;
+            log::info!("mDNS: {}.local", hostname);
+            Ok(Self { mdns })
+        }
+
+        /// 只宣告`<hostname>.local`，不宣告任何服务，用于浏览其它设备
+        pub fn browser(hostname: &str) -> Result<Self> {
+            let mut mdns = EspMdns::take()?;
+            mdns.set_hostname(hostname)?;
+            Ok(Self { mdns })
+        }
+
+        /// 浏览局域网中的服务，例如`browse("_rustled-ota", "_tcp", ..)`
+        pub fn browse(&self, service: &str, proto: &str, timeout: Duration) -> Result<Vec<Discovered>> {
+            let mut results = core::array::from_fn::<QueryResult, MAX_RESULTS, _>(|_| QueryResult {
+                instance_name: None,
+                hostname: None,
+                port: 0,
+                txt: Vec::new(),
+                addr: Vec::new(),
+                interface: Interface::STA,
+                ip_protocol: Protocol::V4,
+            });
+            let len = self.mdns.query_ptr(service, proto, timeout, MAX_RESULTS, &mut results)?;
+            Ok(
+                results
+                    .into_iter()
+                    .take(len)
+                    .map(|result| Discovered {
+                        instance: result.instance_name,
+                        hostname: result.hostname,
+                        port: result.port,
+                        addrs: result.addr,
+                        txt: result.txt,
+                    })
+                    .collect()
+            )
+        }
+
+        /// 找到第一个提供该服务的设备
+        pub fn find(&self, service: &str, proto: &str, timeout: Duration) -> Result<Option<Discovered>> {
+            Ok(self.browse(service, proto, timeout)?.into_iter().find(|d| !d.addrs.is_empty()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{ Ipv4Addr, Ipv6Addr };
+
+    use super::*;
+
+    fn discovered(addrs: Vec<IpAddr>, txt: &[(&str, &str)]) -> Discovered {
+        Discovered {
+            instance: None,
+            hostname: Some("ota-server".into()),
+            port: 5500,
+            addrs,
+            txt: txt
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn url_prefers_ipv4_and_txt_path() {
+        let service = discovered(
+            vec![Ipv6Addr::LOCALHOST.into(), Ipv4Addr::new(192, 168, 88, 235).into()],
+            &[("path", "/firmware/ble_server.bin")]
+        );
+        assert_eq!(service.url("/ota.bin").unwrap(), "http://192.168.88.235:5500/firmware/ble_server.bin");
+        assert_eq!(discovered(vec![], &[]).url("/"), None);
+        let v6 = discovered(vec![Ipv6Addr::LOCALHOST.into()], &[]);
+        assert_eq!(v6.url("ota.bin").unwrap(), "http://[::1]:5500/ota.bin");
+    }
+
+    #[test]
+    fn txt_records_carry_version_and_capabilities() {
+        let txt = txt_records(&["led", "scenes"]);
+        assert_eq!(txt[0], ("version", env!("CARGO_PKG_VERSION").to_string()));
+        assert_eq!(txt[1], ("caps", "led,scenes".to_string()));
+    }
+}
@@ -0,0 +1,178 @@
+//! Wi-Fi
+//!
+//! `connect_wifi`连接一次后返回；`WifiManager`在后台监督连接，断开后自动重连，
+//! 也可以在AP+STA混合模式下同时开启本地热点。
+//! 没有保存任何网络时可以用`portal`进入配网模式。
+#[cfg(target_os = "espidf")]
+use anyhow::{ anyhow, Result };
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::hal::peripheral::Peripheral;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+#[cfg(target_os = "espidf")]
+use esp_idf_svc::wifi::{
+    AccessPointInfo,
+    AuthMethod,
+    BlockingWifi,
+    ClientConfiguration,
+    Configuration,
+    EspWifi,
+    PmfConfiguration,
+};
+
+// 带抖动的指数退避
+mod backoff;
+// 可以被多个线程观察的值
+mod watch;
+mod state;
+// 认证方式的识别和WPA3、开放网络、企业认证的配置
+pub mod auth;
+// 已知网络列表和按信号强度的选择
+pub mod networks;
+// 扫描结果
+pub mod scan;
+// 连接诊断
+pub mod diagnostics;
+// mDNS宣告和服务发现
+pub mod mdns;
+// 固定IP、DNS和主机名
+pub mod ip;
+// 混合模式下的本地热点
+pub mod ap;
+// 配网模式使用的DNS服务器和热点页面
+pub mod dns;
+pub mod portal;
+#[cfg(target_os = "espidf")]
+mod manager;
+pub use ap::ApSettings;
+pub use auth::{ AuthMode, Security };
+pub use backoff::Backoff;
+pub use diagnostics::Diagnostics;
+pub use ip::{ IpSettings, StaticIp };
+pub use networks::KnownNetwork;
+pub use scan::ScanResult;
+pub use state::WifiState;
+pub use watch::{ Subscriber, Watch };
+#[cfg(target_os = "espidf")]
+pub use manager::WifiManager;
+
+/// 根据网络的安全配置和扫描到的认证方式生成站点配置
+///
+/// 企业认证的凭据不在站点配置里，这里会同时设置好；连接其它网络时则关闭企业认证。
+#[cfg(target_os = "espidf")]
+pub fn client_configuration(
+    network: &KnownNetwork,
+    scanned: Option<AuthMode>
+) -> Result<ClientConfiguration> {
+    let mode = network.security.resolve(&network.password, scanned)?;
+    let password = match &network.security {
+        Security::Enterprise(enterprise) => {
+            auth::enable_enterprise(enterprise, &network.password)?;
+            ""
+        }
+        _ => {
+            auth::disable_enterprise()?;
+            network.password.as_str()
+        }
+    };
+    // WPA3必须启用PMF，过渡模式以WPA2为门槛，AP支持时会自动使用WPA3
+    let (auth_method, pmf_cfg) = match mode {
+        AuthMode::Open | AuthMode::Wep => (mode.into(), PmfConfiguration::NotCapable),
+        AuthMode::Wpa3Personal => (AuthMethod::WPA3Personal, PmfConfiguration::Capable { required: true }),
+        AuthMode::Wpa2Wpa3Personal => {
+            (AuthMethod::WPA2Personal, PmfConfiguration::Capable { required: false })
+        }
+        _ => (mode.into(), PmfConfiguration::Capable { required: false }),
+    };
+    log::info!("{}使用认证方式{:?}", network.ssid, mode);
+    Ok(ClientConfiguration {
+        ssid: network.ssid.as_str().try_into().map_err(|_| anyhow!("SSID too long: {}", network.ssid))?,
+        auth_method,
+        password: password.try_into().map_err(|_| anyhow!("password too long"))?,
+        pmf_cfg,
+        ..Default::default()
+    })
+}
+
+/// 扫描结果中目标网络的认证方式，有多个AP时取信号最强的那个
+#[cfg(target_os = "espidf")]
+pub fn scanned_auth(access_points: &[AccessPointInfo], ssid: &str) -> Option<AuthMode> {
+    access_points
+        .iter()
+        .filter(|ap| ap.ssid.as_str() == ssid)
+        .max_by_key(|ap| ap.signal_strength)
+        .and_then(|ap| ap.auth_method)
+        .map(AuthMode::from)
+}
+
+/**
+ * 连接到指定的Wi-Fi网络。
+ *
+ * 此函数初始化Wi-Fi模块，配置连接参数，并尝试连接到指定的Wi-Fi网络。它还负责打印扫描到的Wi-Fi网络信息，
+ * 以及在成功连接后打印设备的IP地址信息。
+ *
+ * @param ssid Wi-Fi网络的SSID。
+ * @param psk Wi-Fi网络的预共享密钥（PSK）。
+ * @param ip_settings 站点接口的IP配置，包括固定IP、DNS和主机名。
+ * @param modem 用于与Wi-Fi模块通信的外设接口。
+ * @param sysloop 系统事件循环，用于处理Wi-Fi相关的事件。
+ * @param nvs NVS（Non-Volatile Storage）分区，用于存储Wi-Fi配置等信息。
+ * @return 返回一个封装了Wi-Fi模块的Box<EspWifi>实例，表示连接成功；如果连接失败，则返回错误。
+ */
+#[cfg(target_os = "espidf")]
+pub fn connect_wifi(
+    ssid: &str,
+    psk: &str,
+    ip_settings: &IpSettings,
+    modem: impl Peripheral<P = esp_idf_svc::hal::modem::Modem> + 'static,
+    sysloop: EspSystemEventLoop,
+    nvs: EspDefaultNvsPartition,
+) -> Result<Box<EspWifi<'static>>> {
+    // 初始化EspWifi实例。
+    let mut esp_wifi = EspWifi::new(modem, sysloop.clone(), Some(nvs))?;
+    // 按配置替换站点接口，使用固定IP或者DHCP，并设置主机名。
+    ip::apply(&mut esp_wifi, ip_settings)?;
+    // 将EspWifi封装为BlockingWifi，以便可以使用阻塞模式的API。
+    let mut wifi = BlockingWifi::wrap(&mut esp_wifi, sysloop)?;
+
+    // 先以站点模式启动，扫描之后才知道目标网络的认证方式。
+    wifi.set_configuration(&Configuration::Client(ClientConfiguration::default()))?;
+    // 启动Wi-Fi模块。
+    log::info!("启动Wi-Fi");
+    wifi.start()?;
+    // 扫描可用的Wi-Fi网络。
+    log::info!("扫描Wi-Fi");
+    let access_point_infos = wifi.scan()?;
+    // 打印扫描结果。
+    log::info!("扫描到的Wi-Fi数量: {}", access_point_infos.len());
+    access_point_infos
+        .iter()
+        .map(ScanResult::from)
+        .for_each(|result| log::info!("{:?}", result));
+
+    // 配置Wi-Fi连接参数，包括SSID、根据扫描结果识别的认证方法和密码。
+    let network = KnownNetwork::new(ssid, psk);
+    let configuration = Configuration::Client(
+        client_configuration(&network, scanned_auth(&access_point_infos, ssid))?
+    );
+    // 应用配置。
+    wifi.set_configuration(&configuration)?;
+
+    // 尝试连接到配置的Wi-Fi网络。
+    log::info!("连接Wi-Fi");
+    wifi.connect()?;
+    // 确认Wi-Fi连接已建立。
+    log::info!("Wi-Fi已连接");
+    // 等待网络接口启动。
+    wifi.wait_netif_up()?;
+
+    // 获取连接后的IP地址信息。
+    let ip_info = wifi.wifi().sta_netif().get_ip_info()?;
+    // 打印IP地址信息。
+    log::info!("IP信息: {:?}", ip_info);
+
+    // 返回封装了Wi-Fi模块的实例。
+    Ok(Box::new(esp_wifi))
+}
@@ -0,0 +1,178 @@
+use serde::{ Deserialize, Serialize };
+
+use super::Security;
+
+/// 已知的Wi-Fi网络，列表中越靠前优先级越高
+///
+/// ```json
+/// { "ssid": "office", "password": "secret" }
+/// { "ssid": "corp", "password": "secret", "security": { "type": "enterprise", "username": "alice" } }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownNetwork {
+    pub ssid: String,
+    #[serde(default)]
+    pub password: String,
+    /// 默认根据扫描结果自动选择认证方式
+    #[serde(default)]
+    pub security: Security,
+}
+
+impl KnownNetwork {
+    pub fn new(ssid: impl Into<String>, password: impl Into<String>) -> Self {
+        Self { ssid: ssid.into(), password: password.into(), security: Security::default() }
+    }
+
+    pub fn with_security(mut self, security: Security) -> Self {
+        self.security = security;
+        self
+    }
+}
+
+/// 按照扫描结果给已知网络排序，决定依次尝试连接的顺序
+///
+/// 扫描到的网络按信号强度从强到弱排在前面，信号相同时按列表顺序；
+/// 没有扫描到的网络（可能是隐藏网络）按列表顺序排在最后。
+/// 同一个SSID有多个AP时取最强的信号。
+pub fn candidates<'a, 's>(
+    known: &'a [KnownNetwork],
+    visible: impl IntoIterator<Item = (&'s str, i8)>
+) -> Vec<&'a KnownNetwork> {
+    let mut rssi: Vec<Option<i8>> = vec![None; known.len()];
+    for (ssid, signal) in visible {
+        for (i, network) in known.iter().enumerate() {
+            if network.ssid == ssid {
+                rssi[i] = Some(rssi[i].map_or(signal, |r| r.max(signal)));
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..known.len()).collect();
+    // 稳定排序，信号相同或者都没有扫描到时保持列表顺序
+    order.sort_by_key(|&i| std::cmp::Reverse(rssi[i].map_or(i16::MIN, |r| r as i16)));
+    order
+        .into_iter()
+        .map(|i| &known[i])
+        .collect()
+}
+
+#[cfg(target_os = "espidf")]
+pub use store::NetworkStore;
+
+#[cfg(target_os = "espidf")]
+mod store {
+    use anyhow::Result;
+    use esp_idf_svc::nvs::{ EspDefaultNvsPartition, EspNvs, NvsDefault };
+
+    use super::KnownNetwork;
+    use crate::{ storage::{ read_json, write_json }, wifi::{ ApSettings, IpSettings } };
+
+    const NAMESPACE: &str = "wifi";
+    const NETWORKS_KEY: &str = "networks";
+    const IP_KEY: &str = "ip";
+    const AP_KEY: &str = "ap";
+
+    /// 保存在NVS中的已知网络列表、站点接口的IP配置和本地热点配置
+    pub struct NetworkStore {
+        nvs: EspNvs<NvsDefault>,
+    }
+
+    impl NetworkStore {
+        pub fn new(partition: EspDefaultNvsPartition) -> Result<Self> {
+            Ok(Self { nvs: EspNvs::new(partition, NAMESPACE, true)? })
+        }
+
+        /// 所有已知网络，按优先级从高到低
+        pub fn list(&self) -> Result<Vec<KnownNetwork>> {
+            Ok(read_json(&self.nvs, NETWORKS_KEY)?.unwrap_or_default())
+        }
+
+        /// 列表为空时先保存编译时配置的网络，兼容原来写在`cfg.toml`里的SSID和密码
+        pub fn list_or_seed(&mut self, ssid: &str, password: &str) -> Result<Vec<KnownNetwork>> {
+            let networks = self.list()?;
+            if !networks.is_empty() || ssid.is_empty() {
+                return Ok(networks);
+            }
+            let networks = vec![KnownNetwork::new(ssid, password)];
+            self.set(&networks)?;
+            Ok(networks)
+        }
+
+        /// 替换整个列表
+        pub fn set(&mut self, networks: &[KnownNetwork]) -> Result<()> {
+            write_json(&mut self.nvs, NETWORKS_KEY, networks)
+        }
+
+        /// 添加网络，同名网络更新密码并保持原来的优先级；新网络排在最后
+        pub fn add(&mut self, network: KnownNetwork) -> Result<()> {
+            let mut networks = self.list()?;
+            match networks.iter_mut().find(|n| n.ssid == network.ssid) {
+                Some(existing) => {
+                    *existing = network;
+                }
+                None => networks.push(network),
+            }
+            self.set(&networks)
+        }
+
+        /// 删除网络，返回网络是否存在
+        pub fn remove(&mut self, ssid: &str) -> Result<bool> {
+            let mut networks = self.list()?;
+            let len = networks.len();
+            networks.retain(|n| n.ssid != ssid);
+            self.set(&networks)?;
+            Ok(networks.len() != len)
+        }
+
+        /// 站点接口的IP配置，没有保存过时使用DHCP和默认主机名
+        pub fn ip_settings(&self) -> Result<IpSettings> {
+            Ok(read_json(&self.nvs, IP_KEY)?.unwrap_or_default())
+        }
+
+        /// 保存IP配置，下次启动Wi-Fi时生效
+        pub fn set_ip_settings(&mut self, settings: &IpSettings) -> Result<()> {
+            settings.validate()?;
+            write_json(&mut self.nvs, IP_KEY, settings)
+        }
+
+        /// 本地热点的配置，没有保存过时使用默认配置
+        pub fn ap_settings(&self) -> Result<ApSettings> {
+            Ok(read_json(&self.nvs, AP_KEY)?.unwrap_or_default())
+        }
+
+        /// 保存热点配置，下次启动Wi-Fi时生效
+        pub fn set_ap_settings(&mut self, settings: &ApSettings) -> Result<()> {
+            settings.validate()?;
+            write_json(&mut self.nvs, AP_KEY, settings)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ssids(networks: Vec<&KnownNetwork>) -> Vec<&str> {
+        networks
+            .into_iter()
+            .map(|n| n.ssid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn strongest_visible_network_first() {
+        let known = [
+            KnownNetwork::new("office", ""),
+            KnownNetwork::new("lab", ""),
+            KnownNetwork::new("home", ""),
+        ];
+        let visible = [("cafe", -30), ("home", -70), ("lab", -55), ("lab", -80)];
+        assert_eq!(ssids(candidates(&known, visible)), ["lab", "home", "office"]);
+    }
+
+    #[test]
+    fn list_order_breaks_ties() {
+        let known = [KnownNetwork::new("a", ""), KnownNetwork::new("b", ""), KnownNetwork::new("c", "")];
+        assert_eq!(ssids(candidates(&known, [("c", -60), ("b", -60)])), ["b", "c", "a"]);
+        assert_eq!(ssids(candidates(&known, [])), ["a", "b", "c"]);
+    }
+}
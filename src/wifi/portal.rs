@@ -0,0 +1,195 @@
+//! 配网模式
+//!
+//! 设备开启一个开放的热点，DNS服务器把所有域名都解析到设备自己，手机连上后会弹出配网页面。
+//! 页面中扫描并选择网络、输入密码，提交后保存到NVS并重启进入站点模式。
+
+/// 配网热点的SSID
+pub const AP_SSID: &str = "ESP32-Setup";
+
+/// 解析`application/x-www-form-urlencoded`格式的表单
+pub fn parse_form(body: &str) -> Vec<(String, String)> {
+    body.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect()
+}
+
+/// 表单中第一个名为`key`的字段
+pub fn form_value<'a>(form: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    form.iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+// 解码`+`和`%XX`，不合法的转义原样保留
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = s
+                    .get(i + 1..i + 3)
+                    .filter(|h| h.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(target_os = "espidf")]
+pub use server::{ button_held, run };
+
+#[cfg(target_os = "espidf")]
+mod server {
+    use std::{ sync::{ mpsc::channel, Arc, Mutex }, time::Duration };
+
+    use anyhow::{ anyhow, bail, Result };
+    use embedded_svc::http::Headers;
+    use esp_idf_svc::{
+        eventloop::EspSystemEventLoop,
+        hal::{ gpio::{ InputPin, OutputPin, PinDriver, Pull }, modem::Modem, peripheral::Peripheral },
+        http::{ server::{ Configuration as HttpConfiguration, EspHttpServer }, Method },
+        io::{ Read, Write },
+        nvs::EspDefaultNvsPartition,
+        wifi::{ AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration, EspWifi },
+    };
+
+    use super::{ form_value, parse_form, AP_SSID };
+    use crate::wifi::{ dns::CaptiveDns, networks::NetworkStore, scan::{ scan, strongest_per_ssid }, KnownNetwork };
+
+    // 表单的最大长度，SSID最长32字节、密码最长64字节，转义后也不会超过
+    const MAX_FORM_LEN: usize = 512;
+
+    /// 启动时按钮（低电平有效）是否被按住
+    pub fn button_held<P: InputPin + OutputPin>(pin: impl Peripheral<P = P>) -> Result<bool> {
+        let mut button = PinDriver::input(pin)?;
+        button.set_pull(Pull::Up)?;
+        // 等上拉稳定
+        std::thread::sleep(Duration::from_millis(10));
+        Ok(button.is_low())
+    }
+
+    /// 进入配网模式，保存网络后重启，正常情况下不会返回
+    pub fn run(
+        modem: impl Peripheral<P = Modem> + 'static,
+        sysloop: EspSystemEventLoop,
+        nvs: EspDefaultNvsPartition
+    ) -> Result<()> {
+        // 同时启用站点接口，用来扫描周围的网络
+        let mut wifi = EspWifi::new(modem, sysloop, Some(nvs.clone()))?;
+        wifi.set_configuration(
+            &Configuration::Mixed(ClientConfiguration::default(), AccessPointConfiguration {
+                ssid: AP_SSID.try_into().map_err(|_| anyhow!("SSID too long"))?,
+                auth_method: AuthMethod::None,
+                channel: 1,
+                ..Default::default()
+            })
+        )?;
+        wifi.start()?;
+        let ip = wifi.ap_netif().get_ip_info()?.ip;
+        log::info!("进入配网模式，热点: {}，地址: {}", AP_SSID, ip);
+
+        let _dns = CaptiveDns::start(ip)?;
+        let wifi = Arc::new(Mutex::new(wifi));
+        let store = Arc::new(Mutex::new(NetworkStore::new(nvs)?));
+        let (done_tx, done_rx) = channel();
+
+        let mut server = EspHttpServer::new(
+            &(HttpConfiguration {
+                stack_size: 10240,
+                // 其它路径都要重定向到配网页面
+                uri_match_wildcard: true,
+                ..Default::default()
+            })
+        )?;
+
+        server.fn_handler("/", Method::Get, |req| {
+            let mut response = req.into_response(200, None, &[("Content-Type", "text/html")])?;
+            response.write_all(include_bytes!("portal.html"))?;
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+        // 扫描周围的网络，同名的AP只保留信号最强的一个
+        server.fn_handler("/scan", Method::Get, move |req| {
+            let results = strongest_per_ssid(scan(&mut wifi.lock().unwrap())?);
+            let mut response = req.into_response(200, None, &[("Content-Type", "application/json")])?;
+            response.write_all(&serde_json::to_vec(&results)?)?;
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+        // 保存提交的网络，新网络排在已知网络的最后
+        server.fn_handler("/connect", Method::Post, move |mut req| {
+            let len = req.content_len().ok_or(anyhow!("content_len is None"))? as usize;
+            if len > MAX_FORM_LEN {
+                bail!("form too large");
+            }
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+            let form = parse_form(std::str::from_utf8(&buf)?);
+            let ssid = form_value(&form, "ssid").unwrap_or_default().trim();
+            if ssid.is_empty() {
+                bail!("missing ssid");
+            }
+            let password = form_value(&form, "password").unwrap_or_default();
+            log::info!("保存Wi-Fi网络: {}", ssid);
+            store.lock().unwrap().add(KnownNetwork::new(ssid, password))?;
+
+            let mut response = req.into_response(200, None, &[("Content-Type", "text/html; charset=utf-8")])?;
+            response.write_all("<p>已保存，设备正在重启…</p>".as_bytes())?;
+            let _ = done_tx.send(());
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+        // 系统访问检测网络的地址时重定向到配网页面，触发“登录网络”的提示
+        let location = format!("http://{}/", ip);
+        server.fn_handler("/*", Method::Get, move |req| {
+            req.into_response(302, Some("Found"), &[("Location", location.as_str())])?;
+            Ok::<(), anyhow::Error>(())
+        })?;
+
+        done_rx.recv()?;
+        // 留时间把响应发出去
+        std::thread::sleep(Duration::from_secs(1));
+        log::info!("重启进入站点模式");
+        esp_idf_svc::hal::reset::restart();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_urlencoded_form() {
+        let form = parse_form("ssid=My+Home%21&password=p%26ss%3Dw%C3%B6rd&empty=&flag");
+        assert_eq!(form_value(&form, "ssid"), Some("My Home!"));
+        assert_eq!(form_value(&form, "password"), Some("p&ss=wörd"));
+        assert_eq!(form_value(&form, "empty"), Some(""));
+        assert_eq!(form_value(&form, "flag"), Some(""));
+        assert_eq!(form_value(&form, "missing"), None);
+    }
+
+    #[test]
+    fn invalid_escapes_are_kept() {
+        let form = parse_form("a=100%&b=%zz%4&c=%+1");
+        assert_eq!(form_value(&form, "a"), Some("100%"));
+        assert_eq!(form_value(&form, "b"), Some("%zz%4"));
+        assert_eq!(form_value(&form, "c"), Some("% 1"));
+    }
+}
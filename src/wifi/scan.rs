@@ -0,0 +1,86 @@
+use serde::Serialize;
+
+use super::AuthMode;
+
+/// 扫描到的AP，供配网页面、手机App选择网络和排查信号覆盖
+///
+/// ```json
+/// { "ssid": "office", "bssid": "24:0a:c4:a1:b2:c3", "channel": 6, "rssi": -52, "auth": "wpa2_personal" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanResult {
+    pub ssid: String,
+    pub bssid: String,
+    pub channel: u8,
+    pub rssi: i8,
+    /// 驱动无法识别认证方式时为`None`
+    pub auth: Option<AuthMode>,
+}
+
+/// 把MAC地址格式化为`aa:bb:cc:dd:ee:ff`
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// 每个SSID只保留信号最强的AP，按信号从强到弱排序，去掉隐藏网络
+pub fn strongest_per_ssid(mut results: Vec<ScanResult>) -> Vec<ScanResult> {
+    results.retain(|r| !r.ssid.is_empty());
+    results.sort_by_key(|r| std::cmp::Reverse(r.rssi));
+    let mut unique: Vec<ScanResult> = Vec::with_capacity(results.len());
+    for result in results {
+        if !unique.iter().any(|r| r.ssid == result.ssid) {
+            unique.push(result);
+        }
+    }
+    unique
+}
+
+#[cfg(target_os = "espidf")]
+impl From<&esp_idf_svc::wifi::AccessPointInfo> for ScanResult {
+    fn from(ap: &esp_idf_svc::wifi::AccessPointInfo) -> Self {
+        Self {
+            ssid: ap.ssid.to_string(),
+            bssid: format_mac(&ap.bssid),
+            channel: ap.channel,
+            rssi: ap.signal_strength,
+            auth: ap.auth_method.map(AuthMode::from),
+        }
+    }
+}
+
+/// 扫描周围的所有AP，按信号从强到弱排序，站点接口必须已经启动
+#[cfg(target_os = "espidf")]
+pub fn scan(wifi: &mut esp_idf_svc::wifi::EspWifi<'_>) -> anyhow::Result<Vec<ScanResult>> {
+    let mut results: Vec<ScanResult> = wifi.scan()?.iter().map(ScanResult::from).collect();
+    results.sort_by_key(|r| std::cmp::Reverse(r.rssi));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(ssid: &str, rssi: i8) -> ScanResult {
+        ScanResult {
+            ssid: ssid.into(),
+            bssid: String::new(),
+            channel: 1,
+            rssi,
+            auth: Some(AuthMode::Wpa2Personal),
+        }
+    }
+
+    #[test]
+    fn keeps_strongest_ap_per_ssid() {
+        let results = vec![result("lab", -80), result("", -20), result("home", -60), result("lab", -40)];
+        assert_eq!(strongest_per_ssid(results), [result("lab", -40), result("home", -60)]);
+    }
+
+    #[test]
+    fn formats_bssid() {
+        assert_eq!(format_mac(&[0x24, 0x0a, 0xc4, 0xa1, 0xb2, 0xc3]), "24:0a:c4:a1:b2:c3");
+    }
+}
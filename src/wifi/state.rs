@@ -0,0 +1,45 @@
+use std::net::Ipv4Addr;
+
+use serde::Serialize;
+
+/// 站点（STA）接口的连接状态
+///
+/// ```json
+/// { "state": "connected", "ssid": "office", "ip": "192.168.1.10", "gateway": "192.168.1.1" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum WifiState {
+    /// Wi-Fi还没有启动
+    Stopped,
+    /// 正在进行第`attempt`次连接
+    Connecting {
+        ssid: String,
+        attempt: u32,
+    },
+    /// 已连接并拿到了IP地址
+    Connected {
+        ssid: String,
+        ip: Ipv4Addr,
+        gateway: Ipv4Addr,
+    },
+    /// 连接断开，`reason`为ESP-IDF的`wifi_err_reason_t`，`retry_in_ms`后重连
+    Disconnected {
+        reason: u16,
+        retry_in_ms: u64,
+    },
+}
+
+impl WifiState {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, WifiState::Connected { .. })
+    }
+
+    /// 已连接时的IP地址
+    pub fn ip(&self) -> Option<Ipv4Addr> {
+        match self {
+            WifiState::Connected { ip, .. } => Some(*ip),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{ sync::{ Arc, Condvar, Mutex }, time::{ Duration, Instant } };
+
+/// 可以被多个线程观察的值，每次`set`都会唤醒等待变化的订阅者
+pub struct Watch<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    // 当前值和版本号，版本号在每次修改后加一
+    value: Mutex<(T, u64)>,
+    condvar: Condvar,
+}
+
+impl<T> Clone for Watch<T> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T: Clone> Watch<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            shared: Arc::new(Shared { value: Mutex::new((value, 0)), condvar: Condvar::new() }),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        self.shared.value.lock().unwrap().0.clone()
+    }
+
+    pub fn set(&self, value: T) {
+        let mut guard = self.shared.value.lock().unwrap();
+        guard.0 = value;
+        guard.1 += 1;
+        self.shared.condvar.notify_all();
+    }
+
+    /// 创建一个订阅者，只会观察到订阅之后的变化
+    pub fn subscribe(&self) -> Subscriber<T> {
+        let version = self.shared.value.lock().unwrap().1;
+        Subscriber { shared: self.shared.clone(), version }
+    }
+}
+
+/// `Watch`的订阅者，连续的多次修改只会观察到最新的值
+pub struct Subscriber<T> {
+    shared: Arc<Shared<T>>,
+    version: u64,
+}
+
+impl<T: Clone> Subscriber<T> {
+    pub fn current(&self) -> T {
+        self.shared.value.lock().unwrap().0.clone()
+    }
+
+    /// 阻塞直到值发生变化，返回新的值
+    pub fn changed(&mut self) -> T {
+        self.wait(|_| true, None).unwrap()
+    }
+
+    /// 阻塞直到值发生变化或超时
+    pub fn changed_timeout(&mut self, timeout: Duration) -> Option<T> {
+        self.wait(|_| true, Some(timeout))
+    }
+
+    /// 阻塞直到当前值满足条件，当前值已经满足时立即返回，`timeout`为`None`表示一直等待
+    pub fn wait_for(&mut self, f: impl Fn(&T) -> bool, timeout: Option<Duration>) -> Option<T> {
+        {
+            let guard = self.shared.value.lock().unwrap();
+            if f(&guard.0) {
+                self.version = guard.1;
+                return Some(guard.0.clone());
+            }
+        }
+        self.wait(f, timeout)
+    }
+
+    // 等待版本号变化并且新的值满足条件
+    fn wait(&mut self, f: impl Fn(&T) -> bool, timeout: Option<Duration>) -> Option<T> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut guard = self.shared.value.lock().unwrap();
+        loop {
+            if guard.1 != self.version {
+                self.version = guard.1;
+                if f(&guard.0) {
+                    return Some(guard.0.clone());
+                }
+            }
+            guard = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return None;
+                    }
+                    self.shared.condvar.wait_timeout(guard, deadline - now).unwrap().0
+                }
+                None => self.shared.condvar.wait(guard).unwrap(),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriber_sees_changes_from_other_threads() {
+        let watch = Watch::new(0);
+        let mut subscriber = watch.subscribe();
+        assert_eq!(subscriber.changed_timeout(Duration::from_millis(10)), None);
+
+        let setter = watch.clone();
+        let thread = std::thread::spawn(move || {
+            for i in 1..=3 {
+                setter.set(i);
+            }
+        });
+        assert_eq!(subscriber.wait_for(|v| *v == 3, Some(Duration::from_secs(1))), Some(3));
+        thread.join().unwrap();
+        assert_eq!(watch.get(), 3);
+    }
+
+    #[test]
+    fn wait_for_returns_current_value_immediately() {
+        let watch = Watch::new("connected");
+        let mut subscriber = watch.subscribe();
+        assert_eq!(subscriber.wait_for(|v| *v == "connected", None), Some("connected"));
+    }
+}